@@ -0,0 +1,10 @@
+-- 代理表（已有部署中的表结构）
+CREATE TABLE IF NOT EXISTS proxies (
+    url VARCHAR NOT NULL,
+    ip VARCHAR NOT NULL,
+    isp VARCHAR,
+    country VARCHAR,
+    latency INTEGER,
+    code VARCHAR,
+    UNIQUE (url, ip)
+);
@@ -0,0 +1,4 @@
+-- 代理健康状态：探测失败时标记为 down，并记录失败时间和连续失败次数
+ALTER TABLE proxies ADD COLUMN IF NOT EXISTS status VARCHAR NOT NULL DEFAULT 'up';
+ALTER TABLE proxies ADD COLUMN IF NOT EXISTS last_failure_at TIMESTAMPTZ;
+ALTER TABLE proxies ADD COLUMN IF NOT EXISTS failure_count INTEGER NOT NULL DEFAULT 0;
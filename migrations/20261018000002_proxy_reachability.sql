@@ -0,0 +1,11 @@
+-- 每个代理对各探测目标的可达性（最近一次探测结果）
+CREATE TABLE IF NOT EXISTS proxy_reachability (
+    url VARCHAR NOT NULL,
+    ip VARCHAR NOT NULL,
+    target VARCHAR NOT NULL,
+    reachable BOOLEAN NOT NULL,
+    status_code INTEGER,
+    checked_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (url, ip, target),
+    FOREIGN KEY (url, ip) REFERENCES proxies (url, ip) ON DELETE CASCADE ON UPDATE CASCADE
+);
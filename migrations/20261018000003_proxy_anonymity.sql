@@ -0,0 +1,2 @@
+-- 代理匿名级别：transparent / anonymous / elite，NULL 表示尚未探测
+ALTER TABLE proxies ADD COLUMN IF NOT EXISTS anonymity VARCHAR;
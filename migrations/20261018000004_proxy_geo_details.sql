@@ -0,0 +1,4 @@
+-- 更详细的出口地理位置：ASN、城市、地区
+ALTER TABLE proxies ADD COLUMN IF NOT EXISTS asn INTEGER;
+ALTER TABLE proxies ADD COLUMN IF NOT EXISTS city VARCHAR;
+ALTER TABLE proxies ADD COLUMN IF NOT EXISTS region VARCHAR;
@@ -0,0 +1,2 @@
+-- 导入来源的代理商名（[[providers]] 的 name）
+ALTER TABLE proxies ADD COLUMN IF NOT EXISTS provider VARCHAR;
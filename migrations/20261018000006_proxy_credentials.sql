@@ -0,0 +1,3 @@
+-- 上游代理的认证信息，与 url 分开存储，转发时通过 Proxy-Authorization 发送
+ALTER TABLE proxies ADD COLUMN IF NOT EXISTS username VARCHAR;
+ALTER TABLE proxies ADD COLUMN IF NOT EXISTS password VARCHAR;
@@ -0,0 +1,2 @@
+-- 代理等级：paid 为代理商提供，free 为公共代理列表，策略默认只使用 paid
+ALTER TABLE proxies ADD COLUMN IF NOT EXISTS tier VARCHAR NOT NULL DEFAULT 'paid';
@@ -0,0 +1,107 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{get, post},
+    Json,
+    Router as AxumRouter,
+};
+use serde_json::json;
+
+use crate::listener::bind_tcp;
+use crate::scheduler::{LatencyScheduler, TriggerSource};
+use crate::shutdown::Shutdown;
+
+// 管理接口，单独监听（默认 127.0.0.1:9090）
+//   GET  /admin/latency      查看探测状态和最近一次结果
+//   POST /admin/latency/run  立即触发一次探测
+pub async fn start_admin_server(bind: String, scheduler: LatencyScheduler, shutdown: Shutdown) {
+    let app = admin_app(scheduler);
+
+    let listener = match bind_tcp(&bind).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            println!("Admin server failed to bind {}: {}", bind, e);
+            return;
+        }
+    };
+
+    println!("Admin server running on http://{}", bind);
+
+    let serve = axum::serve(listener, app)
+        .with_graceful_shutdown(async move { shutdown.wait().await });
+    if let Err(e) = serve.await {
+        println!("Admin server error: {}", e);
+    }
+}
+
+fn admin_app(scheduler: LatencyScheduler) -> AxumRouter {
+    AxumRouter::new()
+        .route("/admin/latency", get(latency_status))
+        .route("/admin/latency/run", post(trigger_latency_update))
+        .with_state(scheduler)
+}
+
+async fn latency_status(State(scheduler): State<LatencyScheduler>) -> impl IntoResponse {
+    Json(json!({
+        "running": scheduler.is_running(),
+        "last_run": scheduler.last_run().await,
+    }))
+}
+
+async fn trigger_latency_update(State(scheduler): State<LatencyScheduler>) -> impl IntoResponse {
+    if scheduler.trigger(TriggerSource::Admin) {
+        (StatusCode::ACCEPTED, Json(json!({ "started": true })))
+    } else {
+        (StatusCode::CONFLICT, Json(json!({ "started": false, "reason": "latency update already running" })))
+    }
+}
+
+#[cfg(test)]
+mod test_admin {
+    use super::*;
+    use crate::config::{GeoConfig, ProbeConfig, SchedulerConfig};
+    use crate::latency::LatencyReport;
+    use crate::scheduler::LastRun;
+    use chrono::Utc;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    #[tokio::test]
+    async fn reports_last_run_and_rejects_concurrent_trigger() {
+        let is_updating = Arc::new(AtomicBool::new(false));
+        let scheduler = LatencyScheduler::new(
+            SchedulerConfig::default(),
+            ProbeConfig::default(),
+            GeoConfig::default(),
+            Arc::clone(&is_updating),
+            Shutdown::new(),
+        );
+        scheduler.record_run(LastRun {
+            trigger: TriggerSource::Scheduled,
+            started_at: Utc::now(),
+            finished_at: Utc::now(),
+            report: LatencyReport { probed: 3, up: 2, down: 1, ..Default::default() },
+        }).await;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, admin_app(scheduler)).await });
+        let client = reqwest::Client::new();
+
+        let status: serde_json::Value = client.get(format!("{}/admin/latency", base))
+            .send().await.unwrap()
+            .json().await.unwrap();
+        assert_eq!(status["running"], false);
+        assert_eq!(status["last_run"]["trigger"], "scheduled");
+        assert_eq!(status["last_run"]["report"]["up"], 2);
+
+        // 已有探测在运行时拒绝触发
+        is_updating.store(true, Ordering::SeqCst);
+        let response = client.post(format!("{}/admin/latency/run", base)).send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::CONFLICT);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["started"], false);
+        assert_eq!(body["reason"], "latency update already running");
+    }
+}
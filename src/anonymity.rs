@@ -0,0 +1,196 @@
+use axum::{
+    extract::ConnectInfo,
+    http::HeaderMap,
+    routing::get,
+    Json,
+    Router as AxumRouter,
+};
+use reqwest::Proxy;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::str::FromStr;
+
+use crate::config::ProbeConfig;
+use crate::exit_ip::ECHO_TIMEOUT;
+use crate::listener::bind_tcp;
+use crate::shutdown::Shutdown;
+use crate::structs::ProxyEndpoint;
+use crate::upstream_tls::upstream_client_builder;
+
+// 会暴露客户端真实IP的转发头
+const FORWARDING_HEADERS: [&str; 6] = ["x-forwarded-for", "x-real-ip", "forwarded", "client-ip", "x-client-ip", "x-originating-ip"];
+// 表明经过代理的头
+const PROXY_HEADERS: [&str; 4] = ["via", "proxy-connection", "x-proxy-id", "proxy-agent"];
+
+// 代理匿名级别，从低到高
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AnonymityLevel {
+    // 透传了客户端真实IP
+    Transparent,
+    // 隐藏了真实IP，但暴露了代理身份（Via等）
+    Anonymous,
+    // 不添加任何代理相关的头
+    Elite,
+}
+
+impl AnonymityLevel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AnonymityLevel::Transparent => "transparent",
+            AnonymityLevel::Anonymous => "anonymous",
+            AnonymityLevel::Elite => "elite",
+        }
+    }
+
+    // 不低于当前级别的所有级别，用于数据库过滤
+    pub fn at_least(&self) -> Vec<String> {
+        [AnonymityLevel::Transparent, AnonymityLevel::Anonymous, AnonymityLevel::Elite]
+            .into_iter()
+            .filter(|level| level >= self)
+            .map(|level| level.as_str().to_string())
+            .collect()
+    }
+}
+
+impl FromStr for AnonymityLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "transparent" => Ok(AnonymityLevel::Transparent),
+            "anonymous" => Ok(AnonymityLevel::Anonymous),
+            "elite" => Ok(AnonymityLevel::Elite),
+            other => Err(format!("unknown anonymity level: {}", other)),
+        }
+    }
+}
+
+// 根据回显的请求头判断匿名级别；real_ip 为 Roxy 自身的公网IP
+pub fn classify_anonymity(headers: &HashMap<String, String>, real_ip: Option<&str>) -> AnonymityLevel {
+    let has_header = |names: &[&str]| headers.keys().any(|name| names.contains(&name.to_lowercase().as_str()));
+
+    if let Some(real_ip) = real_ip
+        && headers.values().any(|value| value.contains(real_ip))
+    {
+        return AnonymityLevel::Transparent;
+    }
+
+    if has_header(&FORWARDING_HEADERS) {
+        // 不知道真实IP时，出现转发头按透明代理处理
+        return if real_ip.is_some() { AnonymityLevel::Anonymous } else { AnonymityLevel::Transparent };
+    }
+
+    if has_header(&PROXY_HEADERS) {
+        return AnonymityLevel::Anonymous;
+    }
+
+    AnonymityLevel::Elite
+}
+
+// 请求头回显服务，探测时代理把请求转发到这里
+pub async fn start_header_echo_server(bind: String, shutdown: Shutdown) {
+    let app = AxumRouter::new().route("/headers", get(echo_headers));
+
+    let listener = match bind_tcp(&bind).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            println!("Header echo server failed to bind {}: {}", bind, e);
+            return;
+        }
+    };
+
+    println!("Header echo server running on http://{}/headers", bind);
+
+    let serve = axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(async move { shutdown.wait().await });
+    if let Err(e) = serve.await {
+        println!("Header echo server error: {}", e);
+    }
+}
+
+async fn echo_headers(ConnectInfo(addr): ConnectInfo<SocketAddr>, headers: HeaderMap) -> Json<serde_json::Value> {
+    let headers: HashMap<String, String> = headers
+        .iter()
+        .map(|(name, value)| (name.as_str().to_string(), String::from_utf8_lossy(value.as_bytes()).to_string()))
+        .collect();
+
+    Json(json!({
+        "remote_addr": addr.ip().to_string(),
+        "headers": headers,
+    }))
+}
+
+#[derive(Deserialize)]
+struct EchoResponse {
+    headers: HashMap<String, String>,
+}
+
+// 通过代理请求回显服务，判断匿名级别
+pub async fn check_anonymity(proxy: Proxy, echo_url: &str, real_ip: Option<&str>) -> Result<AnonymityLevel, reqwest::Error> {
+    let client = upstream_client_builder()
+        .proxy(proxy)
+        .timeout(ECHO_TIMEOUT)
+        .build()?;
+    let echo: EchoResponse = client.get(echo_url).send().await?.json().await?;
+    Ok(classify_anonymity(&echo.headers, real_ip))
+}
+
+// 判断并保存匿名级别
+pub async fn probe_anonymity(proxy: &ProxyEndpoint, probe: &ProbeConfig, real_ip: Option<&str>, db_pool: &PgPool) {
+    let Some(echo_url) = probe.header_echo_url.as_deref() else {
+        return;
+    };
+
+    let result = match proxy.upstream_proxy() {
+        Ok(via) => check_anonymity(via, echo_url, real_ip).await,
+        Err(e) => Err(e),
+    };
+
+    let level = match result {
+        Ok(level) => level,
+        Err(e) => {
+            println!("IP {}: anonymity check failed - {}", proxy.ip, e);
+            return;
+        }
+    };
+
+    match sqlx::query!(
+        "UPDATE proxies SET anonymity = $1 WHERE url = $2 AND ip = $3",
+        level.as_str(),
+        proxy.url,
+        proxy.ip
+    ).execute(db_pool).await {
+        Ok(_) => println!("IP {}: anonymity {}", proxy.ip, level.as_str()),
+        Err(e) => println!("IP {}: anonymity update failed - {}", proxy.ip, e),
+    }
+}
+
+#[cfg(test)]
+mod test_anonymity {
+    use super::*;
+
+    fn headers(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn classify_levels() {
+        let real_ip = Some("203.0.113.7");
+
+        assert_eq!(classify_anonymity(&headers(&[("user-agent", "curl")]), real_ip), AnonymityLevel::Elite);
+        assert_eq!(classify_anonymity(&headers(&[("via", "1.1 squid")]), real_ip), AnonymityLevel::Anonymous);
+        assert_eq!(classify_anonymity(&headers(&[("x-forwarded-for", "198.51.100.9")]), real_ip), AnonymityLevel::Anonymous);
+        assert_eq!(classify_anonymity(&headers(&[("x-forwarded-for", "203.0.113.7")]), real_ip), AnonymityLevel::Transparent);
+        assert_eq!(classify_anonymity(&headers(&[("X-Real-IP", "198.51.100.9")]), None), AnonymityLevel::Transparent);
+    }
+
+    #[test]
+    fn at_least_includes_higher_levels() {
+        assert_eq!(AnonymityLevel::Anonymous.at_least(), vec!["anonymous", "elite"]);
+        assert_eq!(AnonymityLevel::Elite.at_least(), vec!["elite"]);
+    }
+}
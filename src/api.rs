@@ -1,50 +1,243 @@
 use axum::{
     extract::{Request, State},
-    http::{HeaderMap, Method, Uri, StatusCode},
+    http::{HeaderMap, HeaderValue, Method, Uri, StatusCode, Version},
     response::Response,
     Router as AxumRouter,
     body::Body,
 };
-use reqwest::{Client, Proxy};
+use reqwest::Client;
 use dotenvy::dotenv;
+use hyper_util::rt::TokioIo;
 
-use crate::route::Router;
+use crate::anonymity::AnonymityLevel;
+use crate::chain::{chain_hops, send_via_chain, ChainError};
+use crate::config::{ListenerConfig, ReverseRoute, SessionPolicy, Settings, StrategyProfile};
+use crate::listener::{serve_listener, BindAddr, ClientAddr, ProxyListener};
+use crate::ingest::SUPPORTED_SCHEMES;
+use crate::reverse::{load_reverse_routes, match_reverse_route};
+use crate::route::{ProxyFilter, Router};
+use crate::server_tls::start_tls_listener;
+use crate::shutdown::Shutdown;
+use crate::session::{is_valid_country_code, is_valid_session_id, SessionParams};
+use crate::structs::IpInfo;
+use crate::upstream::{open_chain_tunnel, relay_with_idle_timeout, TunnelError};
+use crate::upstream_tls::{certificate_error, upstream_client_builder};
+use std::collections::HashMap;
+use std::net::IpAddr;
 use std::sync::Arc;
-use std::sync::atomic::AtomicBool;
+use std::time::Duration;
 
+// 不转发给目标站点的请求头：逐跳头和 Roxy 自己的控制头
+const PROXY_CONTROL_HEADERS: [&str; 12] = [
+    "host", "connection", "proxy-connection", "content-length", "proxy-authorization",
+    "x-proxy-strategy", "x-proxy-country", "x-proxy-anonymity", "x-proxy-session",
+    "x-proxy-tier", "x-proxy-scheme", "x-proxy-profile",
+];
 
 #[derive(Clone)]
 pub struct AppState {
     pub client: Client,
     pub router: Router,
-    pub is_updating: Arc<AtomicBool>,
+    // 通过 X-Proxy-Profile 选择的策略配置
+    pub profiles: Arc<HashMap<String, StrategyProfile>>,
+    // 反向代理路由
+    pub routes: Arc<Vec<ReverseRoute>>,
+    // 普通请求、隧道和 WebSocket 的空闲超时
+    pub idle_timeout: Duration,
+    pub upstream_http2: bool,
+    // 监听地址的默认策略和会话方式
+    pub defaults: Arc<ListenerDefaults>,
+    // 退出时等待隧道和升级连接结束
+    pub shutdown: Shutdown,
+}
+
+// 请求没有指定策略、profile 或会话时使用的默认值，每个监听地址一份
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListenerDefaults {
+    pub profile: Option<String>,
+    pub strategy: String,
+    pub country: Option<String>,
+    pub session: SessionPolicy,
+    pub session_id: Option<String>,
+}
+
+impl Default for ListenerDefaults {
+    fn default() -> Self {
+        Self {
+            profile: None,
+            strategy: "minlatency".to_string(),
+            country: None,
+            session: SessionPolicy::Rotate,
+            session_id: None,
+        }
+    }
+}
+
+impl ListenerDefaults {
+    // 未知的 profile 忽略；strategy 可写成 country/US
+    pub fn from_config(config: &ListenerConfig, profiles: &HashMap<String, StrategyProfile>) -> Self {
+        let profile = config.profile.clone().filter(|profile| {
+            let known = profiles.contains_key(profile);
+            if !known {
+                println!("Listener {}: unknown profile {}, ignoring", config.bind, profile);
+            }
+            known
+        });
+
+        let (strategy, country) = match (&config.strategy, &config.country) {
+            (Some(strategy), _) => match strategy.split_once('/') {
+                Some((strategy, argument)) => (strategy.to_string(), Some(argument.to_string())),
+                None => (strategy.clone(), config.country.clone()),
+            },
+            (None, Some(country)) => ("country".to_string(), Some(country.clone())),
+            (None, None) => ("minlatency".to_string(), None),
+        };
+
+        if config.session == SessionPolicy::Fixed && config.session_id.is_none() {
+            println!("Listener {}: session = \"fixed\" without session_id, rotating instead", config.bind);
+        }
+
+        Self {
+            profile,
+            strategy,
+            country,
+            session: config.session,
+            session_id: config.session_id.clone(),
+        }
+    }
+
+    // 请求没有 X-Proxy-Session 时的会话ID
+    pub fn session_for(&self, client: Option<IpAddr>) -> Option<String> {
+        match self.session {
+            SessionPolicy::Rotate => None,
+            SessionPolicy::ClientIp => client.map(client_session_id),
+            SessionPolicy::Fixed => self.session_id.clone(),
+        }
+    }
+}
+
+// 由客户端IP生成会话ID，只含数字和字母，可以放进代理商用户名
+fn client_session_id(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(ip) => u32::from(ip).to_string(),
+        IpAddr::V6(ip) => format!("{:x}", u128::from(ip)),
+    }
+}
+
+impl AppState {
+    pub fn new(settings: &Settings, shutdown: Shutdown) -> Self {
+        Self {
+            client: Client::new(),
+            router: Router::new(),
+            profiles: Arc::new(settings.profiles.clone()),
+            routes: Arc::new(load_reverse_routes(&settings.routes, &settings.profiles)),
+            idle_timeout: Duration::from_secs(settings.proxy.idle_timeout_secs),
+            upstream_http2: settings.proxy.upstream_http2,
+            defaults: Arc::new(ListenerDefaults::default()),
+            shutdown,
+        }
+    }
+}
+
+// 一次请求的代理选择条件
+#[derive(Debug, Clone)]
+pub struct ProxySelection {
+    pub strategy: String,
+    pub country: Option<String>,
+    pub filter: ProxyFilter,
+    // X-Proxy-Session，用于代理商用户名模板的粘性会话
+    pub session: Option<String>,
+    // 在选中代理之前经过的固定代理
+    pub chain: Vec<String>,
+}
+
+impl ProxySelection {
+    fn session_params(&self) -> SessionParams {
+        SessionParams {
+            strategy: self.strategy.clone(),
+            // target 等策略的第二段不是国家
+            country: if self.strategy == "country" { self.country.clone() } else { None },
+            session: self.session.clone(),
+        }
+    }
 }
 
 pub async fn start_proxy_server() {
+    start_proxy_server_with_settings(Settings::default(), Shutdown::new()).await;
+}
+
+// 代理服务器启动函数，延迟探测期间照常服务（只选择 status = 'up' 的代理）；收到退出信号后停止接受新连接
+pub async fn start_proxy_server_with_settings(settings: Settings, shutdown: Shutdown) {
     dotenv().ok();
     
-    let state = AppState {
-        client: Client::new(),
-        router: Router::new(),
-        is_updating: Arc::new(AtomicBool::new(false)),
+    let state = AppState::new(&settings, shutdown.clone());
+    let routes = Arc::clone(&state.routes);
+    
+    // HTTPS 代理入口，使用默认的策略
+    if settings.tls.enabled {
+        tokio::spawn(start_tls_listener(settings.tls.clone(), proxy_app(state.clone()), shutdown.clone()));
+    }
+    
+    let listeners = if settings.listeners.is_empty() {
+        vec![ListenerConfig::default()]
+    } else {
+        settings.listeners.clone()
     };
-
-    let app = AxumRouter::new()
-        .fallback(standard_proxy_handler)  // 简化路由，只用fallback
-        .with_state(state);
-
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:8080")
-        .await
-        .unwrap();
+    
+    // 每个监听地址一个 app，携带该监听的默认策略配置；bind 失败时跳过该地址
+    let mut handles = Vec::new();
+    for config in listeners {
+        let bind = BindAddr::parse(&config.bind);
+        let listener = match ProxyListener::bind(&bind).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                println!("Proxy listener failed to bind {}: {}", bind, e);
+                continue;
+            }
+        };
         
-    println!("HTTP Proxy server running on http://0.0.0.0:8080");
+        let defaults = ListenerDefaults::from_config(&config, &state.profiles);
+        // 同一端口接受 HTTP/1.1 和 h2c（prior knowledge，如 curl --http2-prior-knowledge）
+        println!("HTTP Proxy server running on {} (HTTP/1.1 and h2c, default {}/{:?}, profile {:?}, session {:?})",
+            bind, defaults.strategy, defaults.country, defaults.profile, defaults.session);
+        
+        let app = proxy_app(AppState { defaults: Arc::new(defaults), ..state.clone() });
+        handles.push(tokio::spawn(serve_listener(listener, app, shutdown.clone())));
+    }
+    
+    if handles.is_empty() {
+        println!("No proxy listener could be bound, proxy server not started");
+        return;
+    }
+    
     println!("Strategies (using headers):");
     println!("  minlatency: curl --proxy http://localhost:8080 https://api.example.com");
     println!("  random:     curl --proxy http://localhost:8080 -H 'X-Proxy-Strategy: random' https://api.example.com");
     println!("  country:    curl --proxy http://localhost:8080 -H 'X-Proxy-Strategy: country/DE' https://api.example.com");
     println!("  binance:    curl --proxy http://localhost:8080 -H 'X-Proxy-Strategy: binance' https://fapi.binance.com/...");
+    println!("  target:     curl --proxy http://localhost:8080 -H 'X-Proxy-Strategy: target/binance' https://fapi.binance.com/...");
+    println!("Filters (combine with any strategy):");
+    println!("  anonymity:  curl --proxy http://localhost:8080 -H 'X-Proxy-Anonymity: elite' https://api.example.com");
+    println!("  tier:       curl --proxy http://localhost:8080 -H 'X-Proxy-Tier: free' https://api.example.com");
+    println!("  scheme:     curl --proxy http://localhost:8080 -H 'X-Proxy-Scheme: socks5h' https://api.example.com");
+    println!("  session:    curl --proxy http://localhost:8080 -H 'X-Proxy-Session: abc123' https://api.example.com");
+    println!("Profiles ([profiles.<name>] in roxy.toml, may define a proxy chain):");
+    println!("  profile:    curl --proxy http://localhost:8080 -H 'X-Proxy-Profile: compliance' https://api.example.com");
+    println!("WebSocket upgrades (ws:// via the proxy, or through a reverse proxy route) use the same strategies");
+    if !routes.is_empty() {
+        println!("Reverse proxy routes (no proxy support needed):");
+        for route in routes.iter() {
+            println!("  {} -> {} (profile {:?})", route.prefix, route.target, route.profile);
+        }
+    }
     
-    axum::serve(listener, app).await.unwrap();
+    futures_util::future::join_all(handles).await;
+}
+
+fn proxy_app(state: AppState) -> AxumRouter {
+    AxumRouter::new()
+        .fallback(standard_proxy_handler)  // 简化路由，只用fallback
+        .with_state(state)
 }
 
 // 修改 standard_proxy_handler
@@ -58,164 +251,332 @@ async fn standard_proxy_handler(
     
     println!("DEBUG: Received request - Method: {}, URI: {}", method, uri);
     
+    // 反向代理：普通请求（非代理请求）的路径匹配配置的路由前缀
+    let is_origin_request = is_origin_request(&uri, request.version());
+    let reverse = if method != Method::CONNECT && is_origin_request {
+        uri.path_and_query()
+            .and_then(|path_and_query| match_reverse_route(&state.routes, path_and_query.as_str()))
+            .map(|(route, target_url)| (route.clone(), target_url))
+    } else {
+        None
+    };
+    
+    // 路由的策略配置，请求头中的 X-Proxy-Profile 优先
+    let mut headers = headers;
+    if let Some((route, _)) = &reverse
+        && let Some(profile) = &route.profile
+        && !headers.contains_key("X-Proxy-Profile")
+    {
+        let value = HeaderValue::from_str(profile).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        headers.insert("X-Proxy-Profile", value);
+    }
+    
+    // /proxy/<strategy> 前缀只出现在直接请求 Roxy 的请求里，absolute-form 的路径属于目标站点
+    let path = if method == Method::CONNECT || reverse.is_some() || !is_origin_request { "" } else { uri.path() };
+    let client = request.extensions().get::<ClientAddr>().map(|client| client.0.ip());
+    let selection = parse_selection(&state, &headers, path, client)?;
+    
     // 处理HTTPS CONNECT请求
     if method == Method::CONNECT {
-        return handle_connect(state, uri).await;
+        return handle_connect(state, uri, request, selection).await;
+    }
+    
+    let target_url = match reverse {
+        Some((_, target_url)) => target_url,
+        None => forward_target_url(&uri, &headers, is_origin_request)?,
+    };
+    handle_proxy_request(state, method, target_url, headers, request, selection).await
+}
+
+// 从headers中解析附加过滤条件，取值非法时返回400
+pub fn parse_filter_from_headers(headers: &HeaderMap) -> Result<ProxyFilter, StatusCode> {
+    let mut filter = ProxyFilter::default();
+
+    // X-Proxy-Anonymity: transparent / anonymous / elite（最低匿名级别）
+    if let Some(value) = headers.get("X-Proxy-Anonymity") {
+        let level = value.to_str().ok()
+            .and_then(|v| v.parse::<AnonymityLevel>().ok())
+            .ok_or(StatusCode::BAD_REQUEST)?;
+        filter.anonymity = Some(level);
+    }
+
+    // X-Proxy-Tier: free / paid / free,paid / any（默认只用 paid）
+    if let Some(value) = headers.get("X-Proxy-Tier") {
+        let value = value.to_str().map_err(|_| StatusCode::BAD_REQUEST)?.trim().to_lowercase();
+        let tiers: Vec<String> = value.split(',')
+            .map(|tier| tier.trim().to_string())
+            .filter(|tier| !tier.is_empty())
+            .collect();
+        filter.tiers = Some(if tiers.iter().any(|tier| tier == "any") { Vec::new() } else { tiers });
     }
+
+    // X-Proxy-Scheme: socks5 / http,socks5h（上游代理协议）
+    if let Some(value) = headers.get("X-Proxy-Scheme") {
+        let value = value.to_str().map_err(|_| StatusCode::BAD_REQUEST)?.to_lowercase();
+        let schemes: Vec<String> = value.split(',').map(|scheme| scheme.trim().to_string()).collect();
+        if schemes.iter().any(|scheme| !SUPPORTED_SCHEMES.contains(&scheme.as_str())) {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        filter.schemes = Some(schemes);
+    }
+
+    Ok(filter)
+}
+
+// 从请求头（和旧格式的URL路径）解析本次请求的代理选择条件
+pub fn parse_selection(state: &AppState, headers: &HeaderMap, path: &str, client: Option<IpAddr>) -> Result<ProxySelection, StatusCode> {
+    // 附加过滤条件（X-Proxy-Anonymity 等）
+    let filter = parse_filter_from_headers(headers)?;
+    let session = match parse_session_from_headers(headers)? {
+        Some(session) => Some(session),
+        None => state.defaults.session_for(client),
+    };
+    
+    // X-Proxy-Profile: 预先配置的策略和代理链，未指定时使用监听地址的默认配置
+    let profile = match headers.get("X-Proxy-Profile") {
+        Some(value) => {
+            let name = value.to_str().map_err(|_| StatusCode::BAD_REQUEST)?.trim();
+            Some(state.profiles.get(name).cloned().ok_or_else(|| {
+                println!("Unknown profile: {}", name);
+                StatusCode::BAD_REQUEST
+            })?)
+        }
+        None => state.defaults.profile.as_ref().and_then(|name| state.profiles.get(name).cloned()),
+    };
+    let chain = profile.as_ref().map(|profile| profile.chain.clone()).unwrap_or_default();
     
-    // 从headers和URL路径中解析策略
-    let (strategy, country) = parse_strategy_from_request(&headers, uri.path());
+    // 请求中显式指定的策略优先于 profile 的策略
+    let (strategy, country) = match &profile {
+        Some(profile) if !has_explicit_strategy(headers, path) => (profile.strategy.clone(), profile.country.clone()),
+        _ => parse_strategy_from_request(headers, path, &state.defaults),
+    };
     println!("DEBUG: Final parsed strategy: {}, country: {:?}", strategy, country);
     
-    handle_proxy_request(state, method, uri, headers, request, strategy, country).await
+    // 国家代码会填进代理商用户名，只接受两个字母
+    if strategy == "country"
+        && let Some(country) = &country
+        && !is_valid_country_code(country)
+    {
+        println!("Invalid country code: {}", country);
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    
+    Ok(ProxySelection { strategy, country, filter, session, chain })
 }
 
-// 从headers和URL路径中解析策略
-pub fn parse_strategy_from_request(headers: &HeaderMap, path: &str) -> (String, Option<String>) {
+// X-Proxy-Session: <会话ID>，相同会话的请求使用同一出口；只允许 [A-Za-z0-9_]{1,64}，否则返回400
+pub fn parse_session_from_headers(headers: &HeaderMap) -> Result<Option<String>, StatusCode> {
+    let Some(value) = headers.get("X-Proxy-Session") else {
+        return Ok(None);
+    };
+    let session = value.to_str().map_err(|_| StatusCode::BAD_REQUEST)?.trim();
+    if session.is_empty() {
+        return Ok(None);
+    }
+    if !is_valid_session_id(session) {
+        println!("Invalid session id: {}", session);
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    Ok(Some(session.to_string()))
+}
+
+// 请求是否通过路径或 X-Proxy-Strategy / X-Proxy-Country 指定了策略
+fn has_explicit_strategy(headers: &HeaderMap, path: &str) -> bool {
+    parse_strategy_from_path(path).is_some()
+        || headers.contains_key("X-Proxy-Strategy")
+        || headers.contains_key("X-Proxy-Country")
+}
+
+// 从headers和URL路径中解析策略，都没有指定时使用监听地址的默认策略
+pub fn parse_strategy_from_request(headers: &HeaderMap, path: &str, defaults: &ListenerDefaults) -> (String, Option<String>) {
     // 首先检查URL路径中的策略（兼容旧格式）
     if let Some(strategy_from_path) = parse_strategy_from_path(path) {
         return strategy_from_path;
     }
     
     // 然后检查headers中的策略
-    parse_strategy_from_headers(headers)
+    parse_strategy_from_headers(headers, defaults)
 }
 
 // 从URL路径解析策略（支持旧格式）
 fn parse_strategy_from_path(path: &str) -> Option<(String, Option<String>)> {
-    if path.starts_with("/proxy/") {
-        let parts: Vec<&str> = path[7..].split('/').collect(); // 去掉 "/proxy/"
-        if !parts.is_empty() && !parts[0].is_empty() {
-            let strategy = parts[0].to_string();
-            let country = if parts.len() > 1 && !parts[1].is_empty() {
-                Some(parts[1].to_string())
-            } else {
-                None
-            };
-            println!("DEBUG: Parsed strategy from path: {}, country: {:?}", strategy, country);
-            return Some((strategy, country));
-        }
+    let (strategy, country, _) = split_strategy_path(path)?;
+    println!("DEBUG: Parsed strategy from path: {}, country: {:?}", strategy, country);
+    Some((strategy, country))
+}
+
+// 拆分 /proxy/<strategy>[/<参数>]/<剩余路径>?<query>，返回策略、参数和转发给目标站点的路径
+// 只有 country 和 target 策略带参数，其他策略之后的路径段都属于目标路径
+pub fn split_strategy_path(path_and_query: &str) -> Option<(String, Option<String>, String)> {
+    let (path, query) = match path_and_query.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (path_and_query, None),
+    };
+
+    let mut segments = path.strip_prefix("/proxy/")?.splitn(2, '/');
+    let strategy = segments.next().filter(|strategy| !strategy.is_empty())?.to_string();
+    let mut rest = segments.next().unwrap_or_default();
+
+    let mut argument = None;
+    if matches!(strategy.as_str(), "country" | "target") {
+        let (value, remaining) = rest.split_once('/').unwrap_or((rest, ""));
+        argument = Some(value.to_string()).filter(|value| !value.is_empty());
+        rest = remaining;
+    }
+
+    let mut forward_path = format!("/{}", rest);
+    if let Some(query) = query {
+        forward_path.push('?');
+        forward_path.push_str(query);
     }
-    None
+    Some((strategy, argument, forward_path))
 }
 
 // 从headers中解析策略
-pub fn parse_strategy_from_headers(headers: &HeaderMap) -> (String, Option<String>) {
+pub fn parse_strategy_from_headers(headers: &HeaderMap, defaults: &ListenerDefaults) -> (String, Option<String>) {
     // 检查组合策略头 X-Proxy-Strategy: country/DE 或 X-Proxy-Strategy: binance
-    if let Some(strategy_header) = headers.get("X-Proxy-Strategy") {
-        if let Ok(strategy_str) = strategy_header.to_str() {
-            if let Some((strategy, country)) = strategy_str.split_once('/') {
-                println!("DEBUG: Parsed strategy from header: {}, country: {:?}", strategy, Some(country));
-                return (strategy.to_string(), Some(country.to_string()));
-            } else {
-                println!("DEBUG: Parsed strategy from header: {}, country: None", strategy_str);
-                return (strategy_str.to_string(), None);
-            }
+    if let Some(strategy_header) = headers.get("X-Proxy-Strategy")
+        && let Ok(strategy_str) = strategy_header.to_str()
+    {
+        if let Some((strategy, country)) = strategy_str.split_once('/') {
+            println!("DEBUG: Parsed strategy from header: {}, country: {:?}", strategy, Some(country));
+            return (strategy.to_string(), Some(country.to_string()));
+        } else {
+            println!("DEBUG: Parsed strategy from header: {}, country: None", strategy_str);
+            return (strategy_str.to_string(), None);
         }
     }
     
     // 检查分离的国家头
-    if let Some(country_header) = headers.get("X-Proxy-Country") {
-        if let Ok(country_str) = country_header.to_str() {
-            println!("DEBUG: Parsed strategy from separate headers: country, country: {:?}", Some(country_str));
-            return ("country".to_string(), Some(country_str.to_string()));
-        }
+    if let Some(country_header) = headers.get("X-Proxy-Country")
+        && let Ok(country_str) = country_header.to_str()
+    {
+        println!("DEBUG: Parsed strategy from separate headers: country, country: {:?}", Some(country_str));
+        return ("country".to_string(), Some(country_str.to_string()));
     }
     
-    // 默认策略
-    println!("DEBUG: Using default strategy: minlatency");
-    ("minlatency".to_string(), None)
+    // 监听地址的默认策略
+    println!("DEBUG: Using default strategy: {}, country: {:?}", defaults.strategy, defaults.country);
+    (defaults.strategy.clone(), defaults.country.clone())
+}
+
+// 直接请求 Roxy 的请求：HTTP/1 的 origin-form，或 HTTP/2 请求
+// HTTP/2 请求总是带 :scheme 和 :authority，按路径匹配；HTTP/2 的正向代理只支持 CONNECT
+pub fn is_origin_request(uri: &Uri, version: Version) -> bool {
+    uri.scheme().is_none() || version == Version::HTTP_2
+}
+
+// 正向代理请求的目标URL：absolute-form 使用请求中的地址，否则按 :authority 或 Host 头拼接
+// 直接请求时路径中的 /proxy/<strategy>/<country> 只用于选择策略，不转发给目标站点
+pub fn forward_target_url(uri: &Uri, headers: &HeaderMap, is_origin: bool) -> Result<String, StatusCode> {
+    let path_and_query = uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+
+    if !is_origin && let Some(authority) = uri.authority() {
+        return Ok(format!("{}://{}{}", uri.scheme_str().unwrap_or("https"), authority, path_and_query));
+    }
+
+    let path_and_query = match split_strategy_path(path_and_query) {
+        Some((_, _, forward_path)) => forward_path,
+        None => path_and_query.to_string(),
+    };
+    let host = match uri.authority() {
+        Some(authority) => authority.as_str(),
+        None => headers.get("host")
+            .and_then(|h| h.to_str().ok())
+            .ok_or(StatusCode::BAD_REQUEST)?,
+    };
+    // 强制使用HTTPS
+    Ok(format!("https://{}{}", host, path_and_query))
 }
 
 // 核心代理处理逻辑
 pub async fn handle_proxy_request(
     state: AppState,
     method: Method,
-    uri: Uri,
+    target_url: String,
     headers: HeaderMap,
     request: Request<Body>,
-    strategy: String,
-    country: Option<String>,
+    selection: ProxySelection,
 ) -> Result<Response<Body>, StatusCode> {
     
-    // 1. 构建目标URL
-    let target_url = if uri.scheme().is_some() {
-        uri.to_string()
-    } else {
-        let host = headers.get("host")
-            .and_then(|h| h.to_str().ok())
-            .ok_or(StatusCode::BAD_REQUEST)?;
-        // 强制使用HTTPS
-        format!("https://{}{}", host, uri)
-    };
-    
+    // 1. 目标URL（正向代理的请求URL，或反向代理路由的目标）
     println!("Proxying {} request to: {}", method, target_url);
-    println!("Strategy: {}, Country: {:?}", strategy, country);
+    println!("Strategy: {}, Country: {:?}", selection.strategy, selection.country);
     
     // 2. 根据策略获取代理
-    let proxy_info = match strategy.as_str() {
-        "random" => {
-            state.router.get_random_proxy().await
-                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-        }
-        "country" => {
-            if let Some(country_code) = country {
-                state.router.get_proxy_by_country(&country_code).await
-                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-            } else {
-                return Err(StatusCode::BAD_REQUEST);
-            }
-        }
-        "binance" => {
-            state.router.get_binance_proxy().await
-                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-        }
-        _ => {
-            state.router.get_best_proxy().await
-                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-        }
-    };
-    
-    let proxy_info = proxy_info.ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
-    println!("Using proxy: {} ({}ms) - {} ({})", proxy_info.url, proxy_info.latency, proxy_info.country, proxy_info.code);
-    
-    // 3. 创建带代理的客户端
-    let proxy = Proxy::all(&proxy_info.url)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let proxy_info = select_proxy(&state, &selection).await?;
+    println!("Using proxy: {} ({}ms) - {} ({})", proxy_info.redacted_url(), proxy_info.latency, proxy_info.country, proxy_info.code);
     
-    let client = Client::builder()
-        .proxy(proxy)
-        .danger_accept_invalid_certs(true) // 开发环境，生产环境请移除
-        .build()
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    // WebSocket 等协议升级请求：经上游握手后双向转发，不读取请求体
+    if is_upgrade_request(&headers) {
+        return handle_upgrade(state, method, target_url, headers, request, selection, proxy_info).await;
+    }
     
-    // 4. 读取请求体
+    // 3. 读取请求体
     let body_bytes = axum::body::to_bytes(request.into_body(), usize::MAX).await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     
-    // 5. 构建请求 - 过滤代理专用头
-    let mut req_builder = client.request(method, &target_url);
-    
-    // 复制请求头，但排除代理专用头和标准代理头
+    // 4. 复制请求头，但排除代理专用头和标准代理头
+    let mut forward_headers = HeaderMap::new();
     for (name, value) in headers.iter() {
-        let name_str = name.as_str().to_lowercase();
-        if !["host", "connection", "proxy-connection", "content-length", "proxy-authorization",
-             "x-proxy-strategy", "x-proxy-country"].contains(&name_str.as_str()) {
-            req_builder = req_builder.header(name, value);
+        if !PROXY_CONTROL_HEADERS.contains(&name.as_str()) {
+            forward_headers.append(name, value.clone());
         }
     }
     
+    // 配置了代理链时，经过整条链转发
+    if !selection.chain.is_empty() {
+        let hops = chain_hops(&selection.chain, &proxy_info);
+        return match send_via_chain(&hops, method, &target_url, &forward_headers, body_bytes, state.idle_timeout).await {
+            Ok(response) => Ok(response),
+            Err(e @ (ChainError::Timeout | ChainError::Tunnel(TunnelError::Timeout))) => {
+                println!("Request via chain failed: {}", e);
+                Err(StatusCode::GATEWAY_TIMEOUT)
+            }
+            Err(ChainError::Tunnel(e @ TunnelError::Certificate(..))) => {
+                println!("Request via chain failed: {}", e);
+                error_response(StatusCode::BAD_GATEWAY, e.to_string())
+            }
+            Err(e) => {
+                println!("Request via chain failed: {}", e);
+                Err(StatusCode::BAD_GATEWAY)
+            }
+        };
+    }
+    
+    // 5. 创建带代理的客户端（上游认证信息通过 Proxy-Authorization 发送）
+    let proxy = proxy_info.upstream_proxy()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    // 证书校验按 [upstream_tls] 配置（https:// 代理和目标站点）
+    let mut client_builder = upstream_client_builder()
+        .proxy(proxy)
+        .read_timeout(state.idle_timeout);
+    if !state.upstream_http2 {
+        client_builder = client_builder.http1_only();
+    }
+    let client = client_builder.build()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    let mut req_builder = client.request(method, &target_url).headers(forward_headers);
+    
     // 添加请求体
     if !body_bytes.is_empty() {
         req_builder = req_builder.body(body_bytes);
     }
     
     // 6. 发送请求
-    let response = req_builder.send().await
-        .map_err(|e| {
+    let response = match req_builder.send().await {
+        Ok(response) => response,
+        Err(e) => {
+            if let Some(reason) = certificate_error(&e) {
+                println!("Request via {} failed: certificate verification failed - {}", proxy_info.redacted_url(), reason);
+                return error_response(StatusCode::BAD_GATEWAY, format!("certificate verification failed: {}", reason));
+            }
             println!("Request failed: {}", e);
-            StatusCode::BAD_GATEWAY
-        })?;
+            return Err(StatusCode::BAD_GATEWAY);
+        }
+    };
     
     // 7. 构建响应
     let status = response.status();
@@ -235,28 +596,343 @@ pub async fn handle_proxy_request(
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
 }
 
-// 处理HTTPS CONNECT方法
+// Connection: Upgrade 且带 Upgrade 头的请求（如 WebSocket 握手）
+pub fn is_upgrade_request(headers: &HeaderMap) -> bool {
+    let connection_upgrade = headers.get_all("connection").iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|token| token.trim().eq_ignore_ascii_case("upgrade"));
+    connection_upgrade && headers.contains_key("upgrade")
+}
+
+// 协议升级：通过（代理链和）选中的代理把握手请求发给目标站点，
+// 目标返回 101 后把客户端连接和上游连接对接，之后原样转发数据
+async fn handle_upgrade(
+    state: AppState,
+    method: Method,
+    target_url: String,
+    headers: HeaderMap,
+    request: Request<Body>,
+    selection: ProxySelection,
+    proxy_info: IpInfo,
+) -> Result<Response<Body>, StatusCode> {
+    // 保留 Upgrade 和 Sec-WebSocket-* 等握手头，Connection 只保留 upgrade
+    let mut forward_headers = HeaderMap::new();
+    for (name, value) in headers.iter() {
+        if !PROXY_CONTROL_HEADERS.contains(&name.as_str()) {
+            forward_headers.append(name, value.clone());
+        }
+    }
+    forward_headers.insert("connection", HeaderValue::from_static("upgrade"));
+    
+    let hops = chain_hops(&selection.chain, &proxy_info);
+    let mut response = match send_via_chain(&hops, method, &target_url, &forward_headers, Default::default(), state.idle_timeout).await {
+        Ok(response) => response,
+        Err(e) => {
+            println!("Upgrade request to {} via {} failed: {}", target_url, proxy_info.redacted_url(), e);
+            return match e {
+                ChainError::Timeout | ChainError::Tunnel(TunnelError::Timeout) => Err(StatusCode::GATEWAY_TIMEOUT),
+                ChainError::Tunnel(e @ TunnelError::Certificate(..)) => error_response(StatusCode::BAD_GATEWAY, e.to_string()),
+                _ => Err(StatusCode::BAD_GATEWAY),
+            };
+        }
+    };
+    
+    // 目标拒绝升级时按普通响应返回
+    if response.status() != StatusCode::SWITCHING_PROTOCOLS {
+        println!("Upgrade to {} refused with status {}", target_url, response.status());
+        return Ok(response);
+    }
+    
+    let upstream_upgrade = hyper::upgrade::on(&mut response);
+    let idle_timeout = state.idle_timeout;
+    let guard = state.shutdown.guard();
+    tokio::spawn(async move {
+        let _guard = guard;
+        match tokio::try_join!(hyper::upgrade::on(request), upstream_upgrade) {
+            Ok((client, upstream)) => {
+                if let Err(e) = relay_with_idle_timeout(TokioIo::new(client), TokioIo::new(upstream), idle_timeout).await {
+                    println!("Upgraded connection to {} closed: {}", target_url, e);
+                }
+            }
+            Err(e) => println!("Upgrade to {} failed: {}", target_url, e),
+        }
+    });
+    
+    // 101 响应同样不能带 content-length
+    let (parts, _) = response.into_parts();
+    let body = Body::from_stream(futures_util::stream::empty::<Result<Vec<u8>, std::io::Error>>());
+    Ok(Response::from_parts(parts, body))
+}
+
+// 带错误说明的响应
+fn error_response(status: StatusCode, message: String) -> Result<Response<Body>, StatusCode> {
+    Response::builder()
+        .status(status)
+        .body(Body::from(message))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+// 根据策略和过滤条件选择代理
+pub async fn select_proxy(state: &AppState, selection: &ProxySelection) -> Result<IpInfo, StatusCode> {
+    let filter = &selection.filter;
+    let proxy_info = match selection.strategy.as_str() {
+        "random" => {
+            state.router.get_random_proxy(filter).await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        }
+        "country" => {
+            if let Some(country_code) = &selection.country {
+                state.router.get_proxy_by_country(country_code, filter).await
+                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+            } else {
+                return Err(StatusCode::BAD_REQUEST);
+            }
+        }
+        "binance" => {
+            state.router.get_binance_proxy(filter).await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        }
+        "target" => {
+            // X-Proxy-Strategy: target/<探测目标名>
+            if let Some(target) = &selection.country {
+                state.router.get_proxy_for_target(target, filter).await
+                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+            } else {
+                return Err(StatusCode::BAD_REQUEST);
+            }
+        }
+        _ => {
+            state.router.get_best_proxy(filter).await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        }
+    };
+
+    // 填充代理商用户名模板（国家、会话等）
+    proxy_info
+        .map(|info| info.for_session(&selection.session_params()))
+        .ok_or(StatusCode::SERVICE_UNAVAILABLE)
+}
+
+// 处理HTTPS CONNECT方法：先通过上游代理建立隧道，再在客户端与隧道之间转发数据
 pub async fn handle_connect(
     state: AppState,
     uri: Uri,
+    request: Request<Body>,
+    selection: ProxySelection,
 ) -> Result<Response<Body>, StatusCode> {
     
-    let host_port = uri.to_string();
+    let host_port = uri.authority()
+        .map(|authority| authority.to_string())
+        .ok_or(StatusCode::BAD_REQUEST)?;
     println!("CONNECT request to: {}", host_port);
     
-    // 获取最佳代理
-    let proxy_info = state.router.get_best_proxy().await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-        .ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+    // 根据策略获取代理
+    let proxy_info = select_proxy(&state, &selection).await?;
     
-    println!("Using proxy for CONNECT: {} ({}ms)", proxy_info.url, proxy_info.latency);
+    println!("Using proxy for CONNECT: {} ({}ms)", proxy_info.redacted_url(), proxy_info.latency);
     
-    // 对于CONNECT，我们返回200 Connection established
-    // 实际的隧道建立需要更复杂的实现
+    // 配置了代理链时，依次经过链上的代理和选中的代理
+    let hops = chain_hops(&selection.chain, &proxy_info);
+    let upstream = match open_chain_tunnel(&hops, &host_port).await {
+        Ok(upstream) => upstream,
+        Err(e) => {
+            println!("CONNECT via {} failed: {}", proxy_info.redacted_url(), e);
+            return match e {
+                TunnelError::Timeout => Err(StatusCode::GATEWAY_TIMEOUT),
+                TunnelError::Certificate(..) => error_response(StatusCode::BAD_GATEWAY, e.to_string()),
+                _ => Err(StatusCode::BAD_GATEWAY),
+            };
+        }
+    };
+    
+    // 返回 200 后连接升级，开始双向转发
+    let idle_timeout = state.idle_timeout;
+    let guard = state.shutdown.guard();
+    tokio::spawn(async move {
+        let _guard = guard;
+        match hyper::upgrade::on(request).await {
+            Ok(upgraded) => {
+                if let Err(e) = relay_with_idle_timeout(TokioIo::new(upgraded), upstream, idle_timeout).await {
+                    println!("CONNECT tunnel to {} closed: {}", host_port, e);
+                }
+            }
+            Err(e) => println!("CONNECT upgrade failed: {}", e),
+        }
+    });
+    
+    // CONNECT 的 2xx 响应不能带 content-length，用长度未知的空 body 避免 axum 自动添加
+    let body = Body::from_stream(futures_util::stream::empty::<Result<Vec<u8>, std::io::Error>>());
     Response::builder()
         .status(StatusCode::OK)
         .header("Proxy-agent", "roxy/1.0")
-        .body(Body::empty())
+        .body(body)
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
 }
 
+#[cfg(test)]
+mod test_api {
+    use super::*;
+    use crate::config::ListenerTlsConfig;
+
+    #[test]
+    fn strategy_prefix_is_consumed() {
+        assert_eq!(
+            split_strategy_path("/proxy/country/DE/api/v1/time?symbol=BTCUSDT"),
+            Some(("country".to_string(), Some("DE".to_string()), "/api/v1/time?symbol=BTCUSDT".to_string()))
+        );
+        assert_eq!(
+            split_strategy_path("/proxy/random/api/v1"),
+            Some(("random".to_string(), None, "/api/v1".to_string()))
+        );
+        assert_eq!(
+            split_strategy_path("/proxy/target/binance"),
+            Some(("target".to_string(), Some("binance".to_string()), "/".to_string()))
+        );
+        assert_eq!(split_strategy_path("/proxy/random?x=1").unwrap().2, "/?x=1");
+        assert_eq!(split_strategy_path("/proxy/"), None);
+        assert_eq!(split_strategy_path("/api/proxy/random"), None);
+    }
+
+    #[test]
+    fn forwarded_url_drops_prefix() {
+        // absolute-form 的路径属于目标站点，保持不变
+        let uri: Uri = "http://example.com/proxy/country/DE/ip?format=json".parse().unwrap();
+        assert!(!is_origin_request(&uri, Version::HTTP_11));
+        assert_eq!(forward_target_url(&uri, &HeaderMap::new(), false).unwrap(), "http://example.com/proxy/country/DE/ip?format=json");
+
+        let uri: Uri = "/proxy/random/v1/time".parse().unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("host", HeaderValue::from_static("api.example.com"));
+        assert_eq!(forward_target_url(&uri, &headers, true).unwrap(), "https://api.example.com/v1/time");
+
+        let uri: Uri = "http://example.com:8081/plain?q=1".parse().unwrap();
+        assert_eq!(forward_target_url(&uri, &HeaderMap::new(), false).unwrap(), "http://example.com:8081/plain?q=1");
+        assert_eq!(
+            parse_strategy_from_request(&HeaderMap::new(), "/proxy/random/v1", &ListenerDefaults::default()),
+            ("random".to_string(), None)
+        );
+
+        // HTTP/2 请求总是带 :authority，前缀同样用于选择策略并且不转发
+        let request = Request::builder()
+            .version(Version::HTTP_2)
+            .uri("https://api.example.com/proxy/country/DE/ip?format=json")
+            .body(Body::empty())
+            .unwrap();
+        assert!(is_origin_request(request.uri(), request.version()));
+        assert_eq!(forward_target_url(request.uri(), &HeaderMap::new(), true).unwrap(), "https://api.example.com/ip?format=json");
+        assert_eq!(
+            parse_strategy_from_request(&HeaderMap::new(), request.uri().path(), &ListenerDefaults::default()),
+            ("country".to_string(), Some("DE".to_string()))
+        );
+    }
+
+    // h2c 和 TLS 监听（ALPN 优先 h2）上的请求按 /proxy/<strategy> 选择策略
+    // country 策略缺少国家时在查询数据库之前返回 400，说明路径中的策略已生效
+    #[tokio::test]
+    async fn http2_requests_pick_strategy_by_path() {
+        let shutdown = Shutdown::new();
+        let state = AppState::new(&Settings::default(), shutdown.clone());
+
+        let listener = ProxyListener::bind(&BindAddr::parse("127.0.0.1:0")).await.unwrap();
+        let ProxyListener::Tcp(tcp) = &listener else { unreachable!() };
+        let h2c_addr = tcp.local_addr().unwrap();
+        tokio::spawn(serve_listener(listener, proxy_app(state.clone()), shutdown.clone()));
+
+        let client = Client::builder().http2_prior_knowledge().build().unwrap();
+        let response = client.get(format!("http://{}/proxy/country", h2c_addr)).send().await.unwrap();
+        assert_eq!(response.version(), Version::HTTP_2);
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let fixtures = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures");
+        let tls_port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let config = ListenerTlsConfig {
+            enabled: true,
+            bind: format!("127.0.0.1:{}", tls_port),
+            cert_file: Some(format!("{}/localhost.pem", fixtures)),
+            key_file: Some(format!("{}/localhost-key.pem", fixtures)),
+            client_ca_file: None,
+            reload_interval_secs: 0,
+        };
+        tokio::spawn(start_tls_listener(config, proxy_app(state), shutdown.clone()));
+
+        let ca = reqwest::Certificate::from_pem(&std::fs::read(format!("{}/localhost.pem", fixtures)).unwrap()).unwrap();
+        let client = Client::builder().use_rustls_tls().add_root_certificate(ca).build().unwrap();
+        let url = format!("https://localhost:{}/proxy/country", tls_port);
+        let mut response = client.get(&url).send().await;
+        for _ in 0..50 {
+            if response.is_ok() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            response = client.get(&url).send().await;
+        }
+        let response = response.unwrap();
+        assert_eq!(response.version(), Version::HTTP_2);
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        shutdown.trigger();
+    }
+
+    #[test]
+    fn detects_upgrade() {
+        let mut headers = HeaderMap::new();
+        headers.insert("connection", HeaderValue::from_static("keep-alive, Upgrade"));
+        assert!(!is_upgrade_request(&headers));
+        headers.insert("upgrade", HeaderValue::from_static("websocket"));
+        assert!(is_upgrade_request(&headers));
+        headers.insert("connection", HeaderValue::from_static("keep-alive"));
+        assert!(!is_upgrade_request(&headers));
+    }
+
+    #[test]
+    fn listener_defaults() {
+        let config = ListenerConfig {
+            bind: "0.0.0.0:8081".to_string(),
+            strategy: Some("country/US".to_string()),
+            session: SessionPolicy::ClientIp,
+            ..Default::default()
+        };
+        let defaults = ListenerDefaults::from_config(&config, &HashMap::new());
+        assert_eq!(
+            parse_strategy_from_request(&HeaderMap::new(), "/", &defaults),
+            ("country".to_string(), Some("US".to_string()))
+        );
+
+        // 请求头仍然优先
+        let mut headers = HeaderMap::new();
+        headers.insert("X-Proxy-Strategy", HeaderValue::from_static("random"));
+        assert_eq!(parse_strategy_from_request(&headers, "/", &defaults), ("random".to_string(), None));
+
+        assert_eq!(defaults.session_for(Some("10.0.0.1".parse().unwrap())), Some("167772161".to_string()));
+        assert_eq!(defaults.session_for(None), None);
+
+        let config = ListenerConfig { country: Some("DE".to_string()), profile: Some("missing".to_string()), ..Default::default() };
+        let defaults = ListenerDefaults::from_config(&config, &HashMap::new());
+        assert_eq!((defaults.strategy.as_str(), defaults.country.as_deref(), defaults.profile), ("country", Some("DE"), None));
+    }
+
+    // 会话ID和国家代码会拼进代理商用户名，非法取值返回400
+    #[test]
+    fn rejects_unsafe_session_and_country() {
+        let state = AppState::new(&Settings::default(), Shutdown::new());
+        let selection = |pairs: &[(&'static str, &str)]| {
+            let mut headers = HeaderMap::new();
+            for (name, value) in pairs {
+                headers.insert(*name, HeaderValue::from_str(value).unwrap());
+            }
+            parse_selection(&state, &headers, "/", None).map(|selection| (selection.country, selection.session))
+        };
+
+        assert_eq!(
+            selection(&[("X-Proxy-Country", "de"), ("X-Proxy-Session", "abc_123")]),
+            Ok((Some("de".to_string()), Some("abc_123".to_string())))
+        );
+        assert_eq!(selection(&[("X-Proxy-Session", "x-country-us")]), Err(StatusCode::BAD_REQUEST));
+        assert_eq!(selection(&[("X-Proxy-Session", "abc:pass")]), Err(StatusCode::BAD_REQUEST));
+        assert_eq!(selection(&[("X-Proxy-Session", &"a".repeat(65))]), Err(StatusCode::BAD_REQUEST));
+        assert_eq!(selection(&[("X-Proxy-Country", "us-session-x")]), Err(StatusCode::BAD_REQUEST));
+        assert_eq!(selection(&[("X-Proxy-Strategy", "country/D1")]), Err(StatusCode::BAD_REQUEST));
+        // target 策略的参数不是国家代码
+        assert!(selection(&[("X-Proxy-Strategy", "target/binance")]).is_ok());
+    }
+}
@@ -0,0 +1,182 @@
+use axum::body::{Body, Bytes};
+use axum::http::{HeaderMap, Method, Request, Response};
+use hyper::client::conn::http1;
+use hyper_util::rt::TokioIo;
+use reqwest::Url;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::time::timeout;
+
+use crate::structs::IpInfo;
+use crate::upstream::{open_chain_tunnel, tls_connect, TunnelError};
+
+#[derive(Debug, Error)]
+pub enum ChainError {
+    #[error("invalid target url {0}")]
+    InvalidTarget(String),
+    #[error(transparent)]
+    Tunnel(#[from] TunnelError),
+    #[error("http error: {0}")]
+    Http(#[from] hyper::Error),
+    #[error("invalid request: {0}")]
+    Request(#[from] axum::http::Error),
+    #[error("upstream request timed out")]
+    Timeout,
+}
+
+// 代理链：配置的固定代理（如公司出口代理）在前，策略选中的代理在最后
+pub fn chain_hops(chain: &[String], selected: &IpInfo) -> Vec<IpInfo> {
+    chain.iter()
+        .map(|url| IpInfo { url: url.clone(), ..Default::default() })
+        .chain(std::iter::once(selected.clone()))
+        .collect()
+}
+
+// 经过代理链发送一次HTTP请求：先通过整条链建立到目标的隧道，https/wss 目标在隧道内再做TLS
+// 连接支持协议升级，101 响应可以用 hyper::upgrade::on 取得上游连接（WebSocket）
+// 隧道建立后的握手和等待响应头分别受 request_timeout 限制
+pub async fn send_via_chain(
+    hops: &[IpInfo],
+    method: Method,
+    target_url: &str,
+    headers: &HeaderMap,
+    body: Bytes,
+    request_timeout: Duration,
+) -> Result<Response<Body>, ChainError> {
+    let url = Url::parse(target_url).map_err(|_| ChainError::InvalidTarget(target_url.to_string()))?;
+    let host = url.host_str().ok_or_else(|| ChainError::InvalidTarget(target_url.to_string()))?;
+    let port = url.port_or_known_default().ok_or_else(|| ChainError::InvalidTarget(target_url.to_string()))?;
+
+    let mut stream = open_chain_tunnel(hops, &format!("{}:{}", host, port)).await?;
+    if matches!(url.scheme(), "https" | "wss") {
+        stream = tls_connect(stream, host).await?;
+    }
+
+    let (mut sender, connection) = timeout(request_timeout, http1::handshake(TokioIo::new(stream)))
+        .await
+        .map_err(|_| ChainError::Timeout)??;
+    tokio::spawn(async move {
+        if let Err(e) = connection.with_upgrades().await {
+            println!("Chain connection error: {}", e);
+        }
+    });
+
+    let path = match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_string(),
+    };
+    let host_header = match url.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host.to_string(),
+    };
+
+    let mut builder = Request::builder().method(method).uri(path).header("host", host_header);
+    for (name, value) in headers.iter() {
+        builder = builder.header(name, value);
+    }
+    let request = builder.body(Body::from(body))?;
+
+    let response = timeout(request_timeout, sender.send_request(request))
+        .await
+        .map_err(|_| ChainError::Timeout)??;
+    Ok(response.map(Body::new))
+}
+
+#[cfg(test)]
+mod test_chain {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    // 最小的 HTTP CONNECT 代理：连接请求的目标后双向转发
+    async fn fake_connect_proxy() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 1024];
+            let n = stream.read(&mut buf).await.unwrap();
+            let request = String::from_utf8_lossy(&buf[..n]).to_string();
+            let target = request.split_whitespace().nth(1).unwrap().to_string();
+
+            let mut upstream = TcpStream::connect(&target).await.unwrap();
+            stream.write_all(b"HTTP/1.1 200 Connection established\r\n\r\n").await.unwrap();
+            let _ = tokio::io::copy_bidirectional(&mut stream, &mut upstream).await;
+        });
+
+        port
+    }
+
+    #[tokio::test]
+    async fn request_through_two_hops() {
+        let target = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target_port = target.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut stream, _) = target.accept().await.unwrap();
+            let mut buf = [0u8; 1024];
+            let n = stream.read(&mut buf).await.unwrap();
+            assert!(String::from_utf8_lossy(&buf[..n]).starts_with("GET /ip?x=1 HTTP/1.1"));
+            stream.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 5\r\n\r\nhello").await.unwrap();
+        });
+
+        let chain = vec![format!("http://127.0.0.1:{}", fake_connect_proxy().await)];
+        let selected = IpInfo { url: format!("http://127.0.0.1:{}", fake_connect_proxy().await), ..Default::default() };
+        let hops = chain_hops(&chain, &selected);
+        assert_eq!(hops.last().unwrap().url, selected.url);
+
+        let url = format!("http://127.0.0.1:{}/ip?x=1", target_port);
+        let response = send_via_chain(&hops, Method::GET, &url, &HeaderMap::new(), Bytes::new(), Duration::from_secs(5)).await.unwrap();
+        assert_eq!(response.status(), 200);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(&body[..], b"hello");
+    }
+
+    #[tokio::test]
+    async fn websocket_upgrade_through_proxy() {
+        let target = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target_port = target.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut stream, _) = target.accept().await.unwrap();
+            let mut buf = [0u8; 1024];
+            let n = stream.read(&mut buf).await.unwrap();
+            let request = String::from_utf8_lossy(&buf[..n]).to_lowercase();
+            assert!(request.contains("upgrade: websocket"));
+            stream.write_all(b"HTTP/1.1 101 Switching Protocols\r\nconnection: upgrade\r\nupgrade: websocket\r\n\r\n").await.unwrap();
+
+            let n = stream.read(&mut buf).await.unwrap();
+            stream.write_all(&buf[..n]).await.unwrap();
+        });
+
+        let proxy = IpInfo { url: format!("http://127.0.0.1:{}", fake_connect_proxy().await), ..Default::default() };
+        let mut headers = HeaderMap::new();
+        headers.insert("connection", "upgrade".parse().unwrap());
+        headers.insert("upgrade", "websocket".parse().unwrap());
+
+        let url = format!("ws://127.0.0.1:{}/stream", target_port);
+        let mut response = send_via_chain(&chain_hops(&[], &proxy), Method::GET, &url, &headers, Bytes::new(), Duration::from_secs(5)).await.unwrap();
+        assert_eq!(response.status(), 101);
+
+        let mut upgraded = TokioIo::new(hyper::upgrade::on(&mut response).await.unwrap());
+        upgraded.write_all(b"frame").await.unwrap();
+        let mut reply = [0u8; 5];
+        upgraded.read_exact(&mut reply).await.unwrap();
+        assert_eq!(&reply, b"frame");
+    }
+
+    #[tokio::test]
+    async fn times_out_when_target_never_responds() {
+        // 目标接受连接但不返回响应
+        let target = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target_port = target.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (_stream, _) = target.accept().await.unwrap();
+            std::future::pending::<()>().await;
+        });
+
+        let proxy = IpInfo { url: format!("http://127.0.0.1:{}", fake_connect_proxy().await), ..Default::default() };
+        let url = format!("http://127.0.0.1:{}/", target_port);
+        let result = send_via_chain(&chain_hops(&[], &proxy), Method::GET, &url, &HeaderMap::new(), Bytes::new(), Duration::from_millis(200)).await;
+        assert!(matches!(result, Err(ChainError::Timeout)));
+    }
+}
@@ -0,0 +1,522 @@
+use config::{Config, ConfigError, Environment, File};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::env;
+
+use crate::anonymity::AnonymityLevel;
+
+// 代理等级：代理商提供的代理，以及公共代理列表
+pub const TIER_PAID: &str = "paid";
+pub const TIER_FREE: &str = "free";
+
+// 全局配置：从 roxy.toml/yaml/json（或 ROXY_CONFIG 指定的文件）加载，
+// 再由 ROXY__ 前缀的环境变量覆盖，例如 ROXY__SCHEDULER__INTERVAL_SECS=600
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Settings {
+    pub scheduler: SchedulerConfig,
+    pub probe: ProbeConfig,
+    pub ingest: IngestConfig,
+    pub geo: GeoConfig,
+    pub admin: AdminConfig,
+    // 代理商网关，导入时按端口段展开
+    pub providers: Vec<ProviderConfig>,
+    pub upstream_tls: UpstreamTlsConfig,
+    // 按名称选择的策略配置（X-Proxy-Profile）
+    pub profiles: HashMap<String, StrategyProfile>,
+    pub socks: SocksConfig,
+    // 反向代理路由：按路径前缀转发到固定的目标站点
+    pub routes: Vec<ReverseRoute>,
+    pub proxy: ProxyConfig,
+    // HTTPS 代理入口
+    pub tls: ListenerTlsConfig,
+    // HTTP 代理监听地址，未配置时监听 0.0.0.0:8080
+    pub listeners: Vec<ListenerConfig>,
+    // 重启时把监听 socket 交给新进程
+    pub handoff: HandoffConfig,
+}
+
+// 延迟探测调度配置
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct SchedulerConfig {
+    // 启动后首次探测前的等待时间
+    pub initial_delay_secs: u64,
+    // 两次探测之间的间隔
+    pub interval_secs: u64,
+    // 每次间隔额外增加 0..=jitter_secs 的随机时间
+    pub jitter_secs: u64,
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        Self {
+            initial_delay_secs: 300,
+            interval_secs: 900,
+            jitter_secs: 0,
+        }
+    }
+}
+
+// 每个探测周期的配置
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct ProbeConfig {
+    // 整个探测周期的超时时间，未设置时按各项探测的超时、代理数量和并发数计算
+    pub timeout_secs: Option<u64>,
+    // 通过代理访问的目标站点，用于判断代理能否访问特定目的地
+    pub targets: Vec<ProbeTarget>,
+    // IP回显服务（如 https://api.ipify.org），用于检测出口IP变化，变化后按 [geo] 重新定位
+    pub ip_echo_url: Option<String>,
+    // 请求头回显服务的监听地址，需能被代理访问到
+    pub header_echo_bind: Option<String>,
+    // 代理访问回显服务用的URL（http://<公网地址>:<端口>/headers），用于判断匿名级别
+    pub header_echo_url: Option<String>,
+    // ping 和端口连接都失败的连续次数达到该值时删除代理，0 表示不删除
+    pub max_failures: i32,
+    // 同时探测的代理数量
+    pub concurrency: usize,
+}
+
+impl Default for ProbeConfig {
+    fn default() -> Self {
+        Self {
+            timeout_secs: None,
+            targets: Vec::new(),
+            ip_echo_url: None,
+            header_echo_bind: None,
+            header_echo_url: None,
+            max_failures: 10,
+            concurrency: 50,
+        }
+    }
+}
+
+// 可达性探测目标，name 与策略名对应（如 binance）
+#[derive(Debug, Deserialize, Clone)]
+pub struct ProbeTarget {
+    pub name: String,
+    pub url: String,
+    // 允许的状态码，为空时接受任意 2xx
+    #[serde(default)]
+    pub expect_status: Vec<u16>,
+    // 响应体必须包含的内容
+    #[serde(default)]
+    pub body_contains: Option<String>,
+    #[serde(default = "default_probe_target_timeout")]
+    pub timeout_secs: u64,
+}
+
+fn default_probe_target_timeout() -> u64 {
+    5
+}
+
+// 代理导入配置
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct IngestConfig {
+    // 是否在 roxy 进程内定时导入
+    pub enabled: bool,
+    pub initial_delay_secs: u64,
+    pub interval_secs: u64,
+    // 同时获取出口信息的候选代理数量
+    pub concurrency: usize,
+    // 获取出口IP的接口（纯文本IP或JSON），未配置时使用 info_url 环境变量
+    pub info_url: Option<String>,
+    // 网关端口段（连接信息来自 proxy_url / basic_url / proxy_user / proxy_pass 环境变量）
+    pub port_start: u16,
+    pub port_end: u16,
+    // 代理列表文件（纯文本或CSV）
+    pub files: Vec<ProxyListFile>,
+    // 公共代理列表地址
+    pub urls: Vec<ProxyListUrl>,
+    // 公共代理（tier = free）入库前的校验条件
+    pub validation: ValidationConfig,
+}
+
+// 代理列表文件
+#[derive(Debug, Deserialize, Clone)]
+pub struct ProxyListFile {
+    pub path: String,
+    // text 或 csv，未配置时按扩展名判断
+    #[serde(default)]
+    pub format: Option<String>,
+    // 条目未写协议时使用的协议
+    #[serde(default = "default_list_scheme")]
+    pub default_scheme: String,
+    // 写入 proxies.tier，公共代理列表设为 free
+    #[serde(default = "default_file_tier")]
+    pub tier: String,
+}
+
+// 通过HTTP下载的代理列表
+#[derive(Debug, Deserialize, Clone)]
+pub struct ProxyListUrl {
+    pub url: String,
+    // text 或 csv，未配置时按扩展名判断
+    #[serde(default)]
+    pub format: Option<String>,
+    #[serde(default = "default_list_scheme")]
+    pub default_scheme: String,
+    #[serde(default = "default_url_tier")]
+    pub tier: String,
+}
+
+fn default_list_scheme() -> String {
+    "http".to_string()
+}
+
+fn default_file_tier() -> String {
+    TIER_PAID.to_string()
+}
+
+fn default_url_tier() -> String {
+    TIER_FREE.to_string()
+}
+
+// 公共代理的校验：能否转发请求、匿名级别、出口地理位置
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct ValidationConfig {
+    // 最低匿名级别，需要配置 probe.header_echo_url
+    pub min_anonymity: Option<AnonymityLevel>,
+}
+
+impl Default for IngestConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            initial_delay_secs: 0,
+            interval_secs: 86400,
+            concurrency: 20,
+            info_url: None,
+            port_start: 10001,
+            port_end: 10100,
+            files: Vec::new(),
+            urls: Vec::new(),
+            validation: ValidationConfig::default(),
+        }
+    }
+}
+
+// 代理商网关：每个端口对应一个出口
+#[derive(Debug, Deserialize, Clone)]
+pub struct ProviderConfig {
+    // 写入 proxies.provider
+    pub name: String,
+    // 入库地址模板，{port} 替换为端口，如 http://gw.example.com:{port}
+    pub url_template: String,
+    // 获取出口信息时连接的地址模板，未配置时使用 url_template
+    #[serde(default)]
+    pub connect_template: Option<String>,
+    // 端口段，如 ["10001-10100", "10200"]
+    #[serde(default)]
+    pub ports: Vec<String>,
+    // 固定国家出口的端口段
+    #[serde(default)]
+    pub country_ports: Vec<CountryPorts>,
+    #[serde(default)]
+    pub username: Option<String>,
+    // username 是按请求填充的模板（{strategy}、{country}、{session} 等），默认原样使用
+    #[serde(default)]
+    pub username_template: bool,
+    #[serde(default)]
+    pub password: Option<String>,
+    // 从该环境变量读取密码，优先于 password
+    #[serde(default)]
+    pub password_env: Option<String>,
+    #[serde(default)]
+    pub auth: ProviderAuth,
+}
+
+impl ProviderConfig {
+    pub fn password(&self) -> Option<String> {
+        self.password_env.as_ref()
+            .and_then(|name| env::var(name).ok())
+            .or_else(|| self.password.clone())
+    }
+}
+
+// 代理商的认证方式
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderAuth {
+    // 凭据单独存储，转发时通过 Proxy-Authorization 认证，入库地址不含凭据
+    // 旧配置中的 "url" 按 basic 处理
+    #[default]
+    #[serde(alias = "url")]
+    Basic,
+    // IP白名单，无需凭据
+    None,
+}
+
+// 固定国家出口的端口段
+#[derive(Debug, Deserialize, Clone)]
+pub struct CountryPorts {
+    pub country: String,
+    pub ports: Vec<String>,
+}
+
+// 地理位置查询配置
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct GeoConfig {
+    // http：通过代理请求 info_url；maxmind：离线查询 .mmdb 文件
+    pub backend: String,
+    // http 后端的接口，未配置时使用 info_url 环境变量
+    pub info_url: Option<String>,
+    // GeoLite2-City / DB-IP City 数据库
+    pub city_db: Option<String>,
+    // GeoLite2-ASN / DB-IP ASN 数据库
+    pub asn_db: Option<String>,
+}
+
+impl Default for GeoConfig {
+    fn default() -> Self {
+        Self {
+            backend: "http".to_string(),
+            info_url: None,
+            city_db: None,
+            asn_db: None,
+        }
+    }
+}
+
+// 上游代理的TLS配置：https:// 代理的证书，以及转发请求时的目标证书校验
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct UpstreamTlsConfig {
+    // 额外信任的CA证书（PEM，可包含多个），用于自签名的代理证书
+    pub ca_file: Option<String>,
+    // 是否信任内置的根证书
+    pub builtin_roots: bool,
+    // 不校验证书（仅用于调试）
+    pub accept_invalid_certs: bool,
+}
+
+impl Default for UpstreamTlsConfig {
+    fn default() -> Self {
+        Self {
+            ca_file: None,
+            builtin_roots: true,
+            accept_invalid_certs: false,
+        }
+    }
+}
+
+// 策略配置：选择策略、国家，以及在选中代理之前经过的代理链
+// 例如 chain = ["http://corp-egress:3128"] 时，请求依次经过公司出口代理和选中的代理
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct StrategyProfile {
+    pub strategy: String,
+    pub country: Option<String>,
+    pub chain: Vec<String>,
+}
+
+impl Default for StrategyProfile {
+    fn default() -> Self {
+        Self {
+            strategy: "minlatency".to_string(),
+            country: None,
+            chain: Vec::new(),
+        }
+    }
+}
+
+// 代理流量配置
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct ProxyConfig {
+    // 连接两端都没有数据时断开的时间：普通请求、CONNECT 隧道和 WebSocket
+    pub idle_timeout_secs: u64,
+    // 转发请求时允许与目标站点协商 HTTP/2（ALPN），默认只用 HTTP/1.1
+    pub upstream_http2: bool,
+    // 退出（SIGTERM / Ctrl+C）时等待进行中的请求、隧道和延迟更新结束的最长时间
+    pub drain_timeout_secs: u64,
+}
+
+impl Default for ProxyConfig {
+    fn default() -> Self {
+        Self {
+            idle_timeout_secs: 300,
+            upstream_http2: false,
+            drain_timeout_secs: 30,
+        }
+    }
+}
+
+// HTTP 代理监听地址：host:port、[::]:port 或 unix:/path/to/roxy.sock
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct ListenerConfig {
+    pub bind: String,
+    // 请求没有指定 X-Proxy-Profile 时使用的策略配置
+    pub profile: Option<String>,
+    // 请求没有指定策略时的默认策略，如 "random"、"country/US"；只设置 country 时按国家选择
+    pub strategy: Option<String>,
+    pub country: Option<String>,
+    // 请求没有 X-Proxy-Session 时的会话方式
+    pub session: SessionPolicy,
+    // session = "fixed" 时使用的会话ID
+    pub session_id: Option<String>,
+}
+
+impl Default for ListenerConfig {
+    fn default() -> Self {
+        Self {
+            bind: "0.0.0.0:8080".to_string(),
+            profile: None,
+            strategy: None,
+            country: None,
+            session: SessionPolicy::Rotate,
+            session_id: None,
+        }
+    }
+}
+
+// 监听地址的默认会话方式：每个请求轮换出口、同一客户端IP固定出口、所有请求固定出口
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionPolicy {
+    #[default]
+    Rotate,
+    ClientIp,
+    Fixed,
+}
+
+// HTTPS 代理入口（TLS 终止），与 HTTP 入口提供相同的代理服务
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct ListenerTlsConfig {
+    pub enabled: bool,
+    pub bind: String,
+    // PEM 格式的证书链和私钥
+    pub cert_file: Option<String>,
+    pub key_file: Option<String>,
+    // 设置后要求客户端证书，并用该 CA 校验（mTLS）
+    pub client_ca_file: Option<String>,
+    // 检查证书文件变化的间隔，0 表示不自动重新加载
+    pub reload_interval_secs: u64,
+}
+
+impl Default for ListenerTlsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind: "0.0.0.0:8443".to_string(),
+            cert_file: None,
+            key_file: None,
+            client_ca_file: None,
+            reload_interval_secs: 60,
+        }
+    }
+}
+
+// 重启交接：新进程启动时通过 socket 从旧进程接管监听 socket，旧进程随后停止接受连接并等待已有连接结束
+// 由 systemd socket activation 启动时直接使用 systemd 传入的 socket，不需要配置
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct HandoffConfig {
+    // 交接用的 unix socket 路径，未设置时不启用
+    pub socket: Option<String>,
+    // 等待对方发送 socket 或确认的时间
+    pub timeout_secs: u64,
+}
+
+impl Default for HandoffConfig {
+    fn default() -> Self {
+        Self {
+            socket: None,
+            timeout_secs: 5,
+        }
+    }
+}
+
+// 反向代理路由，例如 prefix = "/binance"、target = "https://fapi.binance.com"
+// 时 roxy:8080/binance/fapi/v1/time 转发到 https://fapi.binance.com/fapi/v1/time
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct ReverseRoute {
+    pub prefix: String,
+    pub target: String,
+    // 使用的策略配置（[profiles.<name>]），请求头仍可覆盖策略
+    pub profile: Option<String>,
+}
+
+// SOCKS5 入口配置，与 HTTP 代理使用同一套策略选择
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct SocksConfig {
+    pub enabled: bool,
+    pub bind: String,
+    // 设置后客户端必须使用该密码认证；未设置时接受任意密码，用户名只用来携带策略参数
+    pub password: Option<String>,
+    // UDP ASSOCIATE 没有数据时关闭转发的时间
+    pub udp_idle_timeout_secs: u64,
+}
+
+impl Default for SocksConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind: "0.0.0.0:1080".to_string(),
+            password: None,
+            udp_idle_timeout_secs: 120,
+        }
+    }
+}
+
+// 管理接口配置（单独端口，避免与代理流量混在一起）
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct AdminConfig {
+    pub enabled: bool,
+    pub bind: String,
+}
+
+impl Default for AdminConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            bind: "127.0.0.1:9090".to_string(),
+        }
+    }
+}
+
+impl Settings {
+    pub fn load() -> Result<Self, ConfigError> {
+        let path = env::var("ROXY_CONFIG").unwrap_or_else(|_| "roxy".to_string());
+
+        Config::builder()
+            .add_source(File::with_name(&path).required(false))
+            .add_source(
+                Environment::with_prefix("ROXY")
+                    .prefix_separator("__")
+                    .separator("__")
+                    .try_parsing(true),
+            )
+            .build()?
+            .try_deserialize()
+    }
+
+    // 各服务配置的监听地址，启动时据此判断哪些继承的 socket 还有用
+    pub fn listen_addresses(&self) -> Vec<String> {
+        let mut binds: Vec<String> = if self.listeners.is_empty() {
+            vec![ListenerConfig::default().bind]
+        } else {
+            self.listeners.iter().map(|listener| listener.bind.clone()).collect()
+        };
+        if self.tls.enabled {
+            binds.push(self.tls.bind.clone());
+        }
+        if self.socks.enabled {
+            binds.push(self.socks.bind.clone());
+        }
+        if self.admin.enabled {
+            binds.push(self.admin.bind.clone());
+        }
+        binds.extend(self.probe.header_echo_bind.clone());
+        binds
+    }
+}
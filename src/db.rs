@@ -0,0 +1,15 @@
+use dotenvy::dotenv;
+use std::env;
+use sqlx::PgPool;
+
+// 启动时执行 migrations/ 目录下的数据库迁移
+pub async fn run_migrations() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    dotenv().ok();
+    let pg_url = env::var("DATABASE_URL")?;
+    let pool = PgPool::connect(&pg_url).await?;
+
+    sqlx::migrate!().run(&pool).await?;
+
+    pool.close().await;
+    Ok(())
+}
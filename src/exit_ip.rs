@@ -0,0 +1,173 @@
+use reqwest::{Client, Proxy};
+use serde_json::Value;
+use sqlx::PgPool;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use crate::config::ProbeConfig;
+use crate::geo::{GeoInfo, GeoLocator};
+use crate::structs::ProxyEndpoint;
+use crate::upstream_tls::upstream_client_builder;
+
+// IP回显和请求头回显服务的请求超时
+pub const ECHO_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn proxy_client(proxy: &Proxy) -> Result<Client, reqwest::Error> {
+    upstream_client_builder()
+        .proxy(proxy.clone())
+        .user_agent("curl/8.5.0")
+        .timeout(ECHO_TIMEOUT)
+        .build()
+}
+
+// 解析IP回显服务的响应：纯文本IP，或包含 ip 字段的JSON
+pub fn parse_exit_ip(body: &str) -> Option<String> {
+    let body = body.trim();
+    if let Ok(ip) = body.parse::<IpAddr>() {
+        return Some(ip.to_string());
+    }
+
+    let data: Value = serde_json::from_str(body).ok()?;
+    let ip = data["ip"].as_str().or_else(|| data["proxy"]["ip"].as_str())?;
+    ip.parse::<IpAddr>().ok().map(|ip| ip.to_string())
+}
+
+// 通过代理请求IP回显服务，获取当前出口IP
+pub async fn fetch_exit_ip(proxy: &Proxy, echo_url: &str) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let body = proxy_client(proxy)?.get(echo_url).send().await?.text().await?;
+    parse_exit_ip(&body).ok_or_else(|| format!("unexpected IP echo response: {}", body.trim()).into())
+}
+
+// 不经过代理直接请求IP回显服务，获取 Roxy 自身的公网IP
+pub async fn fetch_public_ip(echo_url: &str) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let client = Client::builder().timeout(ECHO_TIMEOUT).build()?;
+    let body = client.get(echo_url).send().await?.text().await?;
+    parse_exit_ip(&body).ok_or_else(|| format!("unexpected IP echo response: {}", body.trim()).into())
+}
+
+// 检查出口IP是否变化；变化时更新数据库（并重新获取地理位置），返回新的IP
+pub async fn verify_exit_ip(
+    proxy: &ProxyEndpoint,
+    probe: &ProbeConfig,
+    locator: Option<&dyn GeoLocator>,
+    db_pool: &PgPool,
+) -> Option<String> {
+    let echo_url = probe.ip_echo_url.as_deref()?;
+
+    let via = match proxy.upstream_proxy() {
+        Ok(via) => via,
+        Err(e) => {
+            println!("IP {}: invalid proxy url - {}", proxy.ip, e);
+            return None;
+        }
+    };
+
+    let exit_ip = match fetch_exit_ip(&via, echo_url).await {
+        Ok(ip) => ip,
+        Err(e) => {
+            println!("IP {}: exit IP check failed - {}", proxy.ip, e);
+            return None;
+        }
+    };
+
+    if exit_ip == proxy.ip {
+        return None;
+    }
+
+    println!("IP {}: exit IP drifted to {} ({})", proxy.ip, exit_ip, proxy.redacted_url());
+
+    // 出口IP变化后重新定位
+    let geo = match locator {
+        Some(locator) => match locator.locate(&exit_ip, &via).await {
+            Ok(geo) => {
+                println!("IP {}: relocated to {} ({}) {} - {}", exit_ip, geo.country, geo.code, geo.city, geo.isp);
+                Some(geo)
+            }
+            Err(e) => {
+                println!("IP {}: geolocation failed - {}", exit_ip, e);
+                None
+            }
+        },
+        None => None,
+    };
+
+    match move_to_exit_ip(proxy, &exit_ip, geo.as_ref(), db_pool).await {
+        Ok(()) => Some(exit_ip),
+        Err(e) => {
+            println!("IP {}: exit IP update failed - {}", proxy.ip, e);
+            None
+        }
+    }
+}
+
+// 把代理行改到新的出口IP；同一地址在新IP上已有一行时，把本次探测的状态合并到那一行并删除旧行
+async fn move_to_exit_ip(proxy: &ProxyEndpoint, exit_ip: &str, geo: Option<&GeoInfo>, db_pool: &PgPool) -> Result<(), sqlx::Error> {
+    let (isp, country, code, city, region, asn) = match geo {
+        Some(geo) => (Some(&geo.isp), Some(&geo.country), Some(&geo.code), Some(&geo.city), Some(&geo.region), Some(geo.asn)),
+        None => (None, None, None, None, None, None),
+    };
+
+    let mut tx = db_pool.begin().await?;
+
+    let merged = sqlx::query!(
+        r#"
+        UPDATE proxies AS existing
+        SET latency = old.latency, status = old.status, failure_count = old.failure_count, last_failure_at = old.last_failure_at,
+            isp = COALESCE($1, existing.isp), country = COALESCE($2, existing.country), code = COALESCE($3, existing.code),
+            city = COALESCE($4, existing.city), region = COALESCE($5, existing.region), asn = COALESCE($6, existing.asn)
+        FROM proxies AS old
+        WHERE existing.url = $7 AND existing.ip = $8 AND old.url = $7 AND old.ip = $9
+        "#,
+        isp,
+        country,
+        code,
+        city,
+        region,
+        asn,
+        proxy.url,
+        exit_ip,
+        proxy.ip
+    ).execute(&mut *tx).await?;
+
+    if merged.rows_affected() > 0 {
+        sqlx::query!(
+            "DELETE FROM proxies WHERE url = $1 AND ip = $2",
+            proxy.url,
+            proxy.ip
+        ).execute(&mut *tx).await?;
+        println!("IP {}: merged into existing row for {}", proxy.ip, exit_ip);
+    } else {
+        sqlx::query!(
+            r#"
+            UPDATE proxies
+            SET ip = $1, isp = COALESCE($2, isp), country = COALESCE($3, country), code = COALESCE($4, code),
+                city = COALESCE($5, city), region = COALESCE($6, region), asn = COALESCE($7, asn)
+            WHERE url = $8 AND ip = $9
+            "#,
+            exit_ip,
+            isp,
+            country,
+            code,
+            city,
+            region,
+            asn,
+            proxy.url,
+            proxy.ip
+        ).execute(&mut *tx).await?;
+    }
+
+    tx.commit().await
+}
+
+#[cfg(test)]
+mod test_exit_ip {
+    use super::*;
+
+    #[test]
+    fn parse_plain_and_json_echo() {
+        assert_eq!(parse_exit_ip("203.0.113.7\n").as_deref(), Some("203.0.113.7"));
+        assert_eq!(parse_exit_ip(r#"{"ip": "2001:db8::1"}"#).as_deref(), Some("2001:db8::1"));
+        assert_eq!(parse_exit_ip(r#"{"proxy": {"ip": "198.51.100.2"}}"#).as_deref(), Some("198.51.100.2"));
+        assert_eq!(parse_exit_ip("<html>blocked</html>"), None);
+    }
+}
@@ -0,0 +1,195 @@
+use async_trait::async_trait;
+use maxminddb::{geoip2, Reader};
+use reqwest::Proxy;
+use serde_json::Value;
+use std::env;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+
+use crate::config::GeoConfig;
+use crate::upstream_tls::upstream_client_builder;
+
+// http 后端通过代理查询地理位置的超时
+pub const HTTP_LOOKUP_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Error)]
+pub enum GeoError {
+    #[error("geolocation is not configured: {0}")]
+    NotConfigured(String),
+    #[error("geo request failed: {0}")]
+    Request(#[from] reqwest::Error),
+    #[error("invalid geo response: {0}")]
+    InvalidResponse(String),
+    #[error("invalid IP address: {0}")]
+    InvalidIp(String),
+    #[error("mmdb error: {0}")]
+    MaxMind(#[from] maxminddb::MaxMindDBError),
+}
+
+// 出口IP的地理位置信息
+#[derive(Debug, Clone, Default)]
+pub struct GeoInfo {
+    pub isp: String,
+    pub country: String,
+    pub code: String,
+    pub city: String,
+    pub region: String,
+    // 0 表示未知
+    pub asn: i32,
+}
+
+// 地理位置查询后端
+#[async_trait]
+pub trait GeoLocator: Send + Sync {
+    fn name(&self) -> &str;
+
+    // ip 为出口IP；via 为该出口对应的代理（HTTP接口需要通过代理查询）
+    async fn locate(&self, ip: &str, via: &Proxy) -> Result<GeoInfo, GeoError>;
+}
+
+// 通过代理请求 info_url 查询（原 get_proxy_list 的方式）
+pub struct HttpGeoLocator {
+    pub info_url: String,
+}
+
+// 解析 info_url 的响应：isp/country 为必填，city/region/asn 尽量解析
+pub fn parse_http_geo(data: &Value) -> Result<GeoInfo, GeoError> {
+    let required = |value: &Value, name: &str| {
+        value.as_str()
+            .map(str::to_string)
+            .ok_or_else(|| GeoError::InvalidResponse(format!("missing {}", name)))
+    };
+    let optional = |values: &[&Value]| {
+        values.iter()
+            .find_map(|value| value.as_str().or_else(|| value["name"].as_str()))
+            .unwrap_or_default()
+            .to_string()
+    };
+
+    let asn = [&data["isp"]["asn"], &data["asn"]].into_iter()
+        .find_map(|value| {
+            value.as_i64().or_else(|| value.as_str().and_then(|s| s.trim_start_matches("AS").parse().ok()))
+        })
+        .unwrap_or(0);
+
+    Ok(GeoInfo {
+        isp: required(&data["isp"]["isp"], "isp.isp")?,
+        country: required(&data["country"]["name"], "country.name")?,
+        code: required(&data["country"]["code"], "country.code")?,
+        city: optional(&[&data["city"]]),
+        region: optional(&[&data["region"], &data["subdivision"]]),
+        asn: i32::try_from(asn).unwrap_or(0),
+    })
+}
+
+#[async_trait]
+impl GeoLocator for HttpGeoLocator {
+    fn name(&self) -> &str {
+        "http"
+    }
+
+    async fn locate(&self, _ip: &str, via: &Proxy) -> Result<GeoInfo, GeoError> {
+        let client = upstream_client_builder()
+            .proxy(via.clone())
+            .user_agent("curl/8.5.0")
+            .timeout(HTTP_LOOKUP_TIMEOUT)
+            .build()?;
+
+        let data: Value = client.get(&self.info_url).send().await?.error_for_status()?.json().await?;
+        parse_http_geo(&data)
+    }
+}
+
+// 离线查询 GeoLite2 / DB-IP 的 .mmdb 文件
+pub struct MaxMindGeoLocator {
+    city: Option<Reader<Vec<u8>>>,
+    asn: Option<Reader<Vec<u8>>>,
+}
+
+impl MaxMindGeoLocator {
+    pub fn open(city_db: Option<&str>, asn_db: Option<&str>) -> Result<Self, GeoError> {
+        if city_db.is_none() && asn_db.is_none() {
+            return Err(GeoError::NotConfigured("maxmind backend needs city_db or asn_db".to_string()));
+        }
+
+        Ok(Self {
+            city: city_db.map(Reader::open_readfile).transpose()?,
+            asn: asn_db.map(Reader::open_readfile).transpose()?,
+        })
+    }
+}
+
+#[async_trait]
+impl GeoLocator for MaxMindGeoLocator {
+    fn name(&self) -> &str {
+        "maxmind"
+    }
+
+    async fn locate(&self, ip: &str, _via: &Proxy) -> Result<GeoInfo, GeoError> {
+        let addr: IpAddr = ip.parse().map_err(|_| GeoError::InvalidIp(ip.to_string()))?;
+        let mut info = GeoInfo::default();
+
+        let english = |names: &Option<std::collections::BTreeMap<&str, &str>>| {
+            names.as_ref().and_then(|names| names.get("en")).map(|name| name.to_string()).unwrap_or_default()
+        };
+
+        if let Some(reader) = &self.city {
+            let city: geoip2::City = reader.lookup(addr)?;
+            if let Some(country) = &city.country {
+                info.country = english(&country.names);
+                info.code = country.iso_code.unwrap_or_default().to_string();
+            }
+            if let Some(c) = &city.city {
+                info.city = english(&c.names);
+            }
+            if let Some(region) = city.subdivisions.as_ref().and_then(|s| s.first()) {
+                info.region = english(&region.names);
+            }
+        }
+
+        if let Some(reader) = &self.asn {
+            let asn: geoip2::Asn = reader.lookup(addr)?;
+            info.asn = asn.autonomous_system_number.and_then(|n| i32::try_from(n).ok()).unwrap_or(0);
+            info.isp = asn.autonomous_system_organization.unwrap_or_default().to_string();
+        }
+
+        Ok(info)
+    }
+}
+
+// 根据配置创建地理位置后端
+pub fn locator_from_config(config: &GeoConfig) -> Result<Arc<dyn GeoLocator>, GeoError> {
+    match config.backend.as_str() {
+        "maxmind" => Ok(Arc::new(MaxMindGeoLocator::open(config.city_db.as_deref(), config.asn_db.as_deref())?)),
+        "http" => {
+            let info_url = config.info_url.clone()
+                .or_else(|| env::var("info_url").ok())
+                .ok_or_else(|| GeoError::NotConfigured("info_url is not set".to_string()))?;
+            Ok(Arc::new(HttpGeoLocator { info_url }))
+        }
+        other => Err(GeoError::NotConfigured(format!("unknown backend {}", other))),
+    }
+}
+
+#[cfg(test)]
+mod test_geo {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parse_info_url_response() {
+        let data = json!({
+            "proxy": {"ip": "203.0.113.7"},
+            "isp": {"isp": "Example ISP", "asn": "AS64500"},
+            "country": {"name": "Germany", "code": "DE"},
+            "city": {"name": "Berlin"},
+            "region": "Berlin"
+        });
+        let geo = parse_http_geo(&data).unwrap();
+        assert_eq!((geo.code.as_str(), geo.city.as_str(), geo.region.as_str(), geo.asn), ("DE", "Berlin", "Berlin", 64500));
+
+        assert!(parse_http_geo(&json!({"country": {"code": "DE"}})).is_err());
+    }
+}
@@ -0,0 +1,375 @@
+use std::io::{self, Read, Write};
+use std::net::TcpListener as StdTcpListener;
+use std::os::fd::{AsFd, AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net::{UnixListener as StdUnixListener, UnixStream as StdUnixStream};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::net::{TcpListener, UnixListener};
+
+use crate::config::HandoffConfig;
+use crate::shutdown::Shutdown;
+
+// systemd 传入的第一个 fd
+const SD_LISTEN_FDS_START: RawFd = 3;
+// 一次交接最多传递的 socket 数
+const MAX_HANDOFF_FDS: usize = 64;
+// 新进程收到 socket 后回复的确认字节
+const HANDOFF_ACK: u8 = 1;
+
+struct Sockets {
+    // 从 systemd 或旧进程继承、还没有被使用的监听 socket，按监听地址索引
+    inherited: Vec<(String, OwnedFd)>,
+    // 本进程正在使用的监听 socket，交接时发给新进程；配置了 handoff.socket 才记录
+    active: Vec<OwnedFd>,
+    enabled: bool,
+}
+
+static SOCKETS: Mutex<Sockets> = Mutex::new(Sockets {
+    inherited: Vec::new(),
+    active: Vec::new(),
+    enabled: false,
+});
+
+// 监听 socket 的地址，TCP 为 ip:port，unix socket 为 unix:/path
+fn socket_key(fd: &OwnedFd) -> Option<String> {
+    let tcp = StdTcpListener::from(fd.try_clone().ok()?);
+    if let Ok(addr) = tcp.local_addr() {
+        return Some(addr.to_string());
+    }
+    let unix = StdUnixListener::from(OwnedFd::from(tcp));
+    let addr = unix.local_addr().ok()?;
+    addr.as_pathname().map(|path| format!("unix:{}", path.display()))
+}
+
+fn set_cloexec(fd: &OwnedFd) {
+    // ping 等子进程不应继承监听 socket
+    unsafe {
+        libc::fcntl(fd.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC);
+    }
+}
+
+fn add_inherited(fds: Vec<OwnedFd>) -> usize {
+    let mut sockets = SOCKETS.lock().unwrap();
+    for fd in fds {
+        set_cloexec(&fd);
+        match socket_key(&fd) {
+            Some(key) => {
+                println!("Inherited listening socket {}", key);
+                sockets.inherited.push((key, fd));
+            }
+            None => println!("Ignoring inherited fd {}: not a listening socket", fd.as_raw_fd()),
+        }
+    }
+    sockets.inherited.len()
+}
+
+fn take_inherited(key: &str) -> Option<OwnedFd> {
+    let mut sockets = SOCKETS.lock().unwrap();
+    let index = sockets.inherited.iter().position(|(inherited, _)| inherited == key)?;
+    Some(sockets.inherited.remove(index).1)
+}
+
+// 监听地址对应的继承 socket 键，与 inherited_tcp_listener / inherited_unix_listener 的匹配方式一致
+fn bind_key(bind: &str) -> Option<String> {
+    match bind.strip_prefix("unix:") {
+        Some(path) => Some(format!("unix:{}", Path::new(path).display())),
+        None => bind.parse::<std::net::SocketAddr>().ok().map(|addr| addr.to_string()),
+    }
+}
+
+// 在 take_over_listeners 之后、各服务 bind 之前调用：关闭没有任何监听地址对应的继承 socket
+// （新配置删掉了的监听），否则端口会一直被占用，连接进入监听队列后没有人处理
+pub fn close_unclaimed(binds: &[String]) -> usize {
+    let claimed: Vec<String> = binds.iter().filter_map(|bind| bind_key(bind)).collect();
+    let mut sockets = SOCKETS.lock().unwrap();
+    let before = sockets.inherited.len();
+    sockets.inherited.retain(|(key, _)| {
+        let keep = claimed.contains(key);
+        if !keep {
+            println!("Warning: closing inherited socket {}, no listener is configured for it", key);
+        }
+        keep
+    });
+    before - sockets.inherited.len()
+}
+
+// 记录本进程的监听 socket，新进程请求交接时发送
+pub fn register_listener(listener: &impl AsFd) {
+    let mut sockets = SOCKETS.lock().unwrap();
+    if !sockets.enabled {
+        return;
+    }
+    match listener.as_fd().try_clone_to_owned() {
+        Ok(fd) => sockets.active.push(fd),
+        Err(e) => println!("Failed to register listening socket for handoff: {}", e),
+    }
+}
+
+// 继承的 TCP 监听 socket；bind 是 ip:port 形式时按地址匹配
+pub fn inherited_tcp_listener(bind: &str) -> io::Result<Option<TcpListener>> {
+    let Ok(addr) = bind.parse::<std::net::SocketAddr>() else {
+        return Ok(None);
+    };
+    let Some(fd) = take_inherited(&addr.to_string()) else {
+        return Ok(None);
+    };
+
+    let listener = StdTcpListener::from(fd);
+    listener.set_nonblocking(true)?;
+    let listener = TcpListener::from_std(listener)?;
+    register_listener(&listener);
+    println!("Using inherited socket for {}", bind);
+    Ok(Some(listener))
+}
+
+pub fn inherited_unix_listener(path: &Path) -> io::Result<Option<UnixListener>> {
+    let Some(fd) = take_inherited(&format!("unix:{}", path.display())) else {
+        return Ok(None);
+    };
+
+    let listener = StdUnixListener::from(fd);
+    listener.set_nonblocking(true)?;
+    let listener = UnixListener::from_std(listener)?;
+    register_listener(&listener);
+    println!("Using inherited socket for unix:{}", path.display());
+    Ok(Some(listener))
+}
+
+// systemd socket activation：LISTEN_PID 是本进程时，从 fd 3 开始的 LISTEN_FDS 个 fd
+fn systemd_listeners() -> Vec<OwnedFd> {
+    let pid = std::env::var("LISTEN_PID").ok().and_then(|pid| pid.parse::<u32>().ok());
+    if pid != Some(std::process::id()) {
+        return Vec::new();
+    }
+    let count = std::env::var("LISTEN_FDS").ok().and_then(|count| count.parse::<RawFd>().ok()).unwrap_or(0);
+    (SD_LISTEN_FDS_START..SD_LISTEN_FDS_START + count)
+        .map(|fd| unsafe { OwnedFd::from_raw_fd(fd) })
+        .collect()
+}
+
+// 启动时调用，需在各服务 bind 之前：接管 systemd 传入的 socket，
+// 或连接 handoff.socket 从正在运行的旧进程接管监听 socket。返回继承的 socket 数
+pub fn take_over_listeners(config: &HandoffConfig) -> usize {
+    SOCKETS.lock().unwrap().enabled = config.socket.is_some();
+
+    let systemd = systemd_listeners();
+    if !systemd.is_empty() {
+        println!("Received {} sockets from systemd", systemd.len());
+        return add_inherited(systemd);
+    }
+
+    let Some(path) = &config.socket else {
+        return 0;
+    };
+    // 没有旧进程在运行时正常启动
+    let stream = match StdUnixStream::connect(path) {
+        Ok(stream) => stream,
+        Err(_) => return 0,
+    };
+    match request_handoff(stream, Duration::from_secs(config.timeout_secs)) {
+        Ok(fds) => {
+            println!("Took over {} listening sockets from the running process", fds.len());
+            add_inherited(fds)
+        }
+        Err(e) => {
+            println!("Socket handoff from the running process failed, binding normally: {}", e);
+            0
+        }
+    }
+}
+
+fn request_handoff(mut stream: StdUnixStream, timeout: Duration) -> io::Result<Vec<OwnedFd>> {
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    let fds = recv_fds(&stream)?;
+    // 确认之后旧进程才停止接受连接，在此之前新连接留在监听队列里
+    stream.write_all(&[HANDOFF_ACK])?;
+    Ok(fds)
+}
+
+// 旧进程：在 handoff.socket 上等待新进程，发送所有监听 socket，
+// 收到确认后触发退出，停止接受新连接并等待已有连接结束
+pub async fn serve_handoff(config: HandoffConfig, shutdown: Shutdown) {
+    let Some(path) = config.socket else {
+        return;
+    };
+    // 旧进程可能还在监听这个路径（刚完成交接），直接替换
+    let _ = std::fs::remove_file(&path);
+    let listener = match UnixListener::bind(&path) {
+        Ok(listener) => listener,
+        Err(e) => {
+            println!("Handoff socket failed to bind {}: {}", path, e);
+            return;
+        }
+    };
+    println!("Handoff socket listening on {}", path);
+
+    let timeout = Duration::from_secs(config.timeout_secs);
+    loop {
+        let accepted = tokio::select! {
+            accepted = listener.accept() => accepted,
+            _ = shutdown.wait() => return,
+        };
+        let stream = match accepted.and_then(|(stream, _)| stream.into_std()) {
+            Ok(stream) => stream,
+            Err(e) => {
+                println!("Handoff socket accept failed: {}", e);
+                continue;
+            }
+        };
+
+        match tokio::task::spawn_blocking(move || hand_over(stream, timeout)).await {
+            Ok(Ok(count)) => {
+                println!("Handed {} listening sockets to the new process, draining connections", count);
+                shutdown.trigger();
+                return;
+            }
+            Ok(Err(e)) => println!("Socket handoff failed, still serving: {}", e),
+            Err(e) => println!("Socket handoff failed, still serving: {}", e),
+        }
+    }
+}
+
+fn hand_over(mut stream: StdUnixStream, timeout: Duration) -> io::Result<usize> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+
+    let fds: Vec<RawFd> = {
+        let sockets = SOCKETS.lock().unwrap();
+        sockets.active.iter().map(|fd| fd.as_raw_fd()).collect()
+    };
+    send_fds(&stream, &fds)?;
+
+    let mut ack = [0u8; 1];
+    stream.read_exact(&mut ack)?;
+    if ack[0] != HANDOFF_ACK {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "unexpected handoff acknowledgement"));
+    }
+    Ok(fds.len())
+}
+
+// 用 SCM_RIGHTS 发送 fd，数据部分是 fd 数量
+fn send_fds(stream: &StdUnixStream, fds: &[RawFd]) -> io::Result<()> {
+    if fds.is_empty() || fds.len() > MAX_HANDOFF_FDS {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("cannot hand over {} sockets", fds.len())));
+    }
+
+    let payload = (fds.len() as u32).to_be_bytes();
+    let mut iov = libc::iovec {
+        iov_base: payload.as_ptr() as *mut libc::c_void,
+        iov_len: payload.len(),
+    };
+    let data_len = std::mem::size_of_val(fds) as u32;
+    let mut control = vec![0u8; unsafe { libc::CMSG_SPACE(data_len) } as usize];
+
+    unsafe {
+        let mut msg: libc::msghdr = std::mem::zeroed();
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = control.len() as _;
+
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        (*cmsg).cmsg_level = libc::SOL_SOCKET;
+        (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+        (*cmsg).cmsg_len = libc::CMSG_LEN(data_len) as _;
+        std::ptr::copy_nonoverlapping(fds.as_ptr(), libc::CMSG_DATA(cmsg) as *mut RawFd, fds.len());
+
+        if libc::sendmsg(stream.as_raw_fd(), &msg, 0) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+fn recv_fds(stream: &StdUnixStream) -> io::Result<Vec<OwnedFd>> {
+    let mut payload = [0u8; 4];
+    let mut iov = libc::iovec {
+        iov_base: payload.as_mut_ptr() as *mut libc::c_void,
+        iov_len: payload.len(),
+    };
+    let space = unsafe { libc::CMSG_SPACE((MAX_HANDOFF_FDS * std::mem::size_of::<RawFd>()) as u32) } as usize;
+    let mut control = vec![0u8; space];
+
+    let mut fds = Vec::new();
+    let received = unsafe {
+        let mut msg: libc::msghdr = std::mem::zeroed();
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = control.len() as _;
+
+        let received = libc::recvmsg(stream.as_raw_fd(), &mut msg, 0);
+        if received < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+                let data = libc::CMSG_DATA(cmsg) as *const RawFd;
+                let count = ((*cmsg).cmsg_len as usize - libc::CMSG_LEN(0) as usize) / std::mem::size_of::<RawFd>();
+                for i in 0..count {
+                    fds.push(OwnedFd::from_raw_fd(std::ptr::read_unaligned(data.add(i))));
+                }
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+        if msg.msg_flags & libc::MSG_CTRUNC != 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "too many sockets in handoff"));
+        }
+        received
+    };
+
+    if received as usize != payload.len() || u32::from_be_bytes(payload) as usize != fds.len() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "incomplete handoff message"));
+    }
+    Ok(fds)
+}
+
+#[cfg(test)]
+mod test_handoff {
+    use super::*;
+    use crate::config::{ListenerConfig, Settings};
+
+    #[test]
+    fn passes_listening_sockets() {
+        let tcp = StdTcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = tcp.local_addr().unwrap();
+        let (old, new) = StdUnixStream::pair().unwrap();
+
+        send_fds(&old, &[tcp.as_raw_fd()]).unwrap();
+        let fds = recv_fds(&new).unwrap();
+        assert_eq!(fds.len(), 1);
+        assert_eq!(socket_key(&fds[0]), Some(addr.to_string()));
+
+        // 旧进程关闭自己的 fd 后，新进程的 fd 仍在监听同一端口
+        drop(tcp);
+        let listener = StdTcpListener::from(fds.into_iter().next().unwrap());
+        let client = std::net::TcpStream::connect(addr).unwrap();
+        let (accepted, _) = listener.accept().unwrap();
+        assert_eq!(accepted.peer_addr().unwrap(), client.local_addr().unwrap());
+    }
+
+    #[test]
+    fn closes_sockets_dropped_from_config() {
+        let kept = StdTcpListener::bind("127.0.0.1:0").unwrap();
+        let dropped = StdTcpListener::bind("127.0.0.1:0").unwrap();
+        let kept_addr = kept.local_addr().unwrap();
+        let dropped_addr = dropped.local_addr().unwrap();
+        add_inherited(vec![OwnedFd::from(kept), OwnedFd::from(dropped)]);
+
+        // 新配置只保留了一个监听地址
+        let settings = Settings {
+            listeners: vec![ListenerConfig { bind: kept_addr.to_string(), ..Default::default() }],
+            ..Default::default()
+        };
+        assert_eq!(close_unclaimed(&settings.listen_addresses()), 1);
+
+        // 删掉的监听已经关闭，端口可以重新绑定；保留的仍可接管
+        assert!(StdTcpListener::bind(dropped_addr).is_ok());
+        assert!(take_inherited(&kept_addr.to_string()).is_some());
+    }
+}
@@ -0,0 +1,424 @@
+use async_trait::async_trait;
+use reqwest::Url;
+use std::path::{Path, PathBuf};
+
+use super::{CandidateBatch, IngestError, IngestFailure, ProxyCandidate, ProxySource};
+use crate::config::{ProxyListFile, ProxyListUrl};
+
+// 支持的上游代理协议
+pub const SUPPORTED_SCHEMES: [&str; 6] = ["http", "https", "socks4", "socks4a", "socks5", "socks5h"];
+
+// 解析代理URL：地址规范化为 scheme://host:port，URL中的 user:pass 移到 username/password，
+// 入库地址（proxies.url，唯一键的一部分）不含凭据
+pub fn parse_proxy_url(raw: &str) -> Result<ProxyCandidate, String> {
+    let url = Url::parse(raw).map_err(|e| format!("invalid proxy url: {}", e))?;
+
+    let scheme = url.scheme();
+    if !SUPPORTED_SCHEMES.contains(&scheme) {
+        return Err(format!("unsupported scheme: {}", scheme));
+    }
+
+    let host = url.host_str()
+        .filter(|host| !host.is_empty())
+        .ok_or("missing host")?;
+
+    let port = url.port_or_known_default()
+        .or(if scheme.starts_with("socks") { Some(1080) } else { None })
+        .ok_or("missing port")?;
+
+    let decode = |s: &str| urlencoding::decode(s).map(|s| s.into_owned()).unwrap_or_else(|_| s.to_string());
+    let mut candidate = ProxyCandidate::new(format!("{}://{}:{}", scheme, host, port));
+    if !url.username().is_empty() {
+        candidate.username = Some(decode(url.username()));
+        candidate.password = url.password().map(decode);
+    }
+
+    Ok(candidate)
+}
+
+// 解析一行代理：host:port、host:port:user:pass、user:pass@host:port、scheme://...
+// IPv6 地址需要写成 [addr]:port
+pub fn parse_proxy_line(line: &str, default_scheme: &str) -> Result<ProxyCandidate, String> {
+    let line = line.trim();
+
+    let raw = if line.contains("://") {
+        line.to_string()
+    } else {
+        let parts: Vec<&str> = line.split(':').collect();
+        if !line.contains('@') && !line.starts_with('[') && parts.len() == 4 {
+            format!(
+                "{}://{}:{}@{}:{}",
+                default_scheme,
+                urlencoding::encode(parts[2]),
+                urlencoding::encode(parts[3]),
+                parts[0],
+                parts[1]
+            )
+        } else {
+            format!("{}://{}", default_scheme, line)
+        }
+    };
+
+    parse_proxy_url(&raw)
+}
+
+fn is_skipped(line: &str) -> bool {
+    let line = line.trim();
+    line.is_empty() || line.starts_with('#')
+}
+
+// 解析纯文本列表，每行一个代理；返回 (候选代理, (行号, 错误))
+pub fn parse_text_list(content: &str, default_scheme: &str) -> (Vec<ProxyCandidate>, Vec<(usize, String)>) {
+    let mut candidates = Vec::new();
+    let mut errors = Vec::new();
+
+    for (index, line) in content.lines().enumerate() {
+        if is_skipped(line) {
+            continue;
+        }
+        match parse_proxy_line(line, default_scheme) {
+            Ok(candidate) => candidates.push(candidate),
+            Err(e) => errors.push((index + 1, e)),
+        }
+    }
+
+    (candidates, errors)
+}
+
+// 按逗号拆分一行CSV，支持双引号包裹的字段
+fn split_csv_row(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => fields.push(std::mem::take(&mut field).trim().to_string()),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field.trim().to_string());
+    fields
+}
+
+// CSV 各列的位置
+#[derive(Debug, Default)]
+struct CsvColumns {
+    url: Option<usize>,
+    host: Option<usize>,
+    port: Option<usize>,
+    username: Option<usize>,
+    password: Option<usize>,
+    scheme: Option<usize>,
+}
+
+impl CsvColumns {
+    // 识别表头；不是表头时返回 None
+    fn from_header(row: &[String]) -> Option<Self> {
+        let mut columns = CsvColumns::default();
+        for (index, name) in row.iter().enumerate() {
+            let slot = match name.to_lowercase().as_str() {
+                "proxy" | "url" => &mut columns.url,
+                "host" | "ip" | "address" | "server" => &mut columns.host,
+                "port" => &mut columns.port,
+                "username" | "user" | "login" => &mut columns.username,
+                "password" | "pass" => &mut columns.password,
+                "protocol" | "scheme" | "type" => &mut columns.scheme,
+                _ => continue,
+            };
+            slot.get_or_insert(index);
+        }
+
+        if columns.url.is_some() || (columns.host.is_some() && columns.port.is_some()) {
+            Some(columns)
+        } else {
+            None
+        }
+    }
+
+    // 无表头：单列为代理地址，多列为 host,port[,user,pass]
+    fn positional(width: usize) -> Self {
+        if width == 1 {
+            CsvColumns { url: Some(0), ..Default::default() }
+        } else {
+            CsvColumns {
+                host: Some(0),
+                port: Some(1),
+                username: if width > 2 { Some(2) } else { None },
+                password: if width > 3 { Some(3) } else { None },
+                ..Default::default()
+            }
+        }
+    }
+
+    fn parse_row(&self, row: &[String], default_scheme: &str) -> Result<ProxyCandidate, String> {
+        let cell = |index: Option<usize>| index.and_then(|i| row.get(i)).map(String::as_str).filter(|v| !v.is_empty());
+
+        let scheme = cell(self.scheme).map(str::to_lowercase).unwrap_or_else(|| default_scheme.to_string());
+
+        if let Some(url) = cell(self.url) {
+            return parse_proxy_line(url, &scheme);
+        }
+
+        let host = cell(self.host).ok_or("missing host")?;
+        let port = cell(self.port).ok_or("missing port")?;
+        // 未加方括号的 IPv6 地址
+        let host = if host.contains(':') && !host.starts_with('[') {
+            format!("[{}]", host)
+        } else {
+            host.to_string()
+        };
+
+        let userinfo = match (cell(self.username), cell(self.password)) {
+            (Some(user), Some(pass)) => format!("{}:{}@", urlencoding::encode(user), urlencoding::encode(pass)),
+            (Some(user), None) => format!("{}@", urlencoding::encode(user)),
+            _ => String::new(),
+        };
+
+        parse_proxy_url(&format!("{}://{}{}:{}", scheme, userinfo, host, port))
+    }
+}
+
+// 解析CSV列表；返回 (候选代理, (行号, 错误))
+pub fn parse_csv_list(content: &str, default_scheme: &str) -> (Vec<ProxyCandidate>, Vec<(usize, String)>) {
+    let mut candidates = Vec::new();
+    let mut errors = Vec::new();
+    let mut header: Option<CsvColumns> = None;
+    let mut first_row = true;
+
+    for (index, line) in content.lines().enumerate() {
+        if is_skipped(line) {
+            continue;
+        }
+        let row = split_csv_row(line);
+
+        // 第一行可能是表头
+        if first_row {
+            first_row = false;
+            if let Some(columns) = CsvColumns::from_header(&row) {
+                header = Some(columns);
+                continue;
+            }
+        }
+
+        let result = match &header {
+            Some(columns) => columns.parse_row(&row, default_scheme),
+            None => CsvColumns::positional(row.len()).parse_row(&row, default_scheme),
+        };
+
+        match result {
+            Ok(candidate) => candidates.push(candidate),
+            Err(e) => errors.push((index + 1, e)),
+        }
+    }
+
+    (candidates, errors)
+}
+
+async fn read_list(path: &Path) -> Result<String, IngestError> {
+    tokio::fs::read_to_string(path).await
+        .map_err(|e| IngestError::Source(format!("failed to read {}: {}", path.display(), e)))
+}
+
+// 解析列表内容并标记代理等级；origin 用于错误信息中的 文件:行号
+fn into_batch(origin: &str, content: &str, is_csv: bool, default_scheme: &str, tier: &str) -> CandidateBatch {
+    let (candidates, errors) = if is_csv {
+        parse_csv_list(content, default_scheme)
+    } else {
+        parse_text_list(content, default_scheme)
+    };
+
+    CandidateBatch {
+        candidates: candidates.into_iter()
+            .map(|candidate| ProxyCandidate { tier: tier.to_string(), ..candidate })
+            .collect(),
+        rejected: errors.into_iter()
+            .map(|(line, error)| IngestFailure { candidate: format!("{}:{}", origin, line), error })
+            .collect(),
+    }
+}
+
+// format 未指定时按扩展名判断
+fn is_csv_format(format: Option<&str>, name: &str) -> bool {
+    match format {
+        Some(format) => format.eq_ignore_ascii_case("csv"),
+        None => Path::new(name).extension().is_some_and(|ext| ext.eq_ignore_ascii_case("csv")),
+    }
+}
+
+// 纯文本代理列表文件
+#[derive(Debug, Clone)]
+pub struct TextListSource {
+    pub path: PathBuf,
+    pub default_scheme: String,
+    pub tier: String,
+}
+
+#[async_trait]
+impl ProxySource for TextListSource {
+    fn name(&self) -> &str {
+        "text-list"
+    }
+
+    async fn candidates(&self) -> Result<CandidateBatch, IngestError> {
+        let content = read_list(&self.path).await?;
+        Ok(into_batch(&self.path.display().to_string(), &content, false, &self.default_scheme, &self.tier))
+    }
+}
+
+// CSV 代理列表文件
+#[derive(Debug, Clone)]
+pub struct CsvListSource {
+    pub path: PathBuf,
+    pub default_scheme: String,
+    pub tier: String,
+}
+
+#[async_trait]
+impl ProxySource for CsvListSource {
+    fn name(&self) -> &str {
+        "csv-list"
+    }
+
+    async fn candidates(&self) -> Result<CandidateBatch, IngestError> {
+        let content = read_list(&self.path).await?;
+        Ok(into_batch(&self.path.display().to_string(), &content, true, &self.default_scheme, &self.tier))
+    }
+}
+
+// 根据配置创建文件来源
+pub fn file_source(file: &ProxyListFile) -> Box<dyn ProxySource> {
+    let path = PathBuf::from(&file.path);
+    let default_scheme = file.default_scheme.clone();
+    let tier = file.tier.clone();
+
+    if is_csv_format(file.format.as_deref(), &file.path) {
+        Box::new(CsvListSource { path, default_scheme, tier })
+    } else {
+        Box::new(TextListSource { path, default_scheme, tier })
+    }
+}
+
+// 公共代理列表地址，每次导入时重新下载
+#[derive(Debug, Clone)]
+pub struct UrlListSource {
+    pub url: String,
+    pub is_csv: bool,
+    pub default_scheme: String,
+    pub tier: String,
+}
+
+impl UrlListSource {
+    pub fn new(list: &ProxyListUrl) -> Self {
+        let path = Url::parse(&list.url).map(|url| url.path().to_string()).unwrap_or_default();
+        Self {
+            url: list.url.clone(),
+            is_csv: is_csv_format(list.format.as_deref(), &path),
+            default_scheme: list.default_scheme.clone(),
+            tier: list.tier.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl ProxySource for UrlListSource {
+    fn name(&self) -> &str {
+        &self.url
+    }
+
+    async fn candidates(&self) -> Result<CandidateBatch, IngestError> {
+        let client = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(30))
+            .build()?;
+        let content = client.get(&self.url).send().await?.error_for_status()?.text().await?;
+        Ok(into_batch(&self.url, &content, self.is_csv, &self.default_scheme, &self.tier))
+    }
+}
+
+#[cfg(test)]
+mod test_list {
+    use super::*;
+
+    fn urls(candidates: &[ProxyCandidate]) -> Vec<&str> {
+        candidates.iter().map(|c| c.url.as_str()).collect()
+    }
+
+    fn credentials(candidate: &ProxyCandidate) -> (Option<&str>, Option<&str>) {
+        (candidate.username.as_deref(), candidate.password.as_deref())
+    }
+
+    #[test]
+    fn parse_text_formats() {
+        let content = "\
+# vendor A
+1.2.3.4:8080
+user:p@ss@5.6.7.8:3128
+9.9.9.9:1080:alice:secret
+socks5h://bob:pw@proxy.example.com:1081
+[2001:db8::1]:8080
+socks5://[2001:db8::2]
+
+not a proxy
+ftp://1.2.3.4:21
+";
+        let (candidates, errors) = parse_text_list(content, "http");
+
+        assert_eq!(urls(&candidates), vec![
+            "http://1.2.3.4:8080",
+            "http://5.6.7.8:3128",
+            "http://9.9.9.9:1080",
+            "socks5h://proxy.example.com:1081",
+            "http://[2001:db8::1]:8080",
+            "socks5://[2001:db8::2]:1080",
+        ]);
+        assert_eq!(credentials(&candidates[0]), (None, None));
+        assert_eq!(credentials(&candidates[1]), (Some("user"), Some("p@ss")));
+        assert_eq!(credentials(&candidates[2]), (Some("alice"), Some("secret")));
+        assert_eq!(credentials(&candidates[3]), (Some("bob"), Some("pw")));
+        assert_eq!(errors.iter().map(|(line, _)| *line).collect::<Vec<_>>(), vec![9, 10]);
+    }
+
+    #[test]
+    fn parse_csv_with_header() {
+        let content = "\
+Protocol,IP,Port,Username,Password,Country
+socks5,10.0.0.1,1080,u1,\"p,1\",DE
+http,2001:db8::3,8080,,,US
+https,10.0.0.2,,u2,p2,FR
+";
+        let (candidates, errors) = parse_csv_list(content, "http");
+
+        assert_eq!(urls(&candidates), vec![
+            "socks5://10.0.0.1:1080",
+            "http://[2001:db8::3]:8080",
+        ]);
+        assert_eq!(credentials(&candidates[0]), (Some("u1"), Some("p,1")));
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, 4);
+    }
+
+    #[test]
+    fn parse_csv_without_header() {
+        let (candidates, errors) = parse_csv_list("10.0.0.1,3128\n10.0.0.2,3129,user,pass\n", "http");
+        assert_eq!(urls(&candidates), vec!["http://10.0.0.1:3128", "http://10.0.0.2:3129"]);
+        assert_eq!(credentials(&candidates[1]), (Some("user"), Some("pass")));
+        assert!(errors.is_empty());
+
+        let (candidates, _) = parse_csv_list("socks5://10.0.0.3:1080\n", "http");
+        assert_eq!(urls(&candidates), vec!["socks5://10.0.0.3:1080"]);
+    }
+
+    #[test]
+    fn batch_carries_tier() {
+        let batch = into_batch("https://lists.example.com/http.txt", "1.2.3.4:8080\nftp://1.2.3.4:21\n", false, "http", "free");
+        assert_eq!(batch.candidates[0].tier, "free");
+        assert_eq!(batch.rejected[0].candidate, "https://lists.example.com/http.txt:2");
+        assert!(is_csv_format(None, "/lists/proxies.CSV"));
+    }
+}
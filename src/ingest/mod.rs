@@ -0,0 +1,428 @@
+use async_trait::async_trait;
+use dotenvy::dotenv;
+use reqwest::Proxy;
+use sqlx::PgPool;
+use std::collections::HashSet;
+use std::env;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+use crate::anonymity::AnonymityLevel;
+use crate::config::{IngestConfig, ProviderConfig, Settings, TIER_FREE, TIER_PAID};
+use crate::exit_ip::fetch_exit_ip;
+use crate::geo::{locator_from_config, GeoError, GeoLocator};
+use crate::shutdown::Shutdown;
+use crate::structs::{IpInfo, REDACTED};
+use crate::upstream::{redact_proxy_url, upstream_proxy};
+
+pub mod provider;
+pub use provider::*;
+
+pub mod list;
+pub use list::*;
+
+pub mod validate;
+pub use validate::*;
+
+#[derive(Debug, Error)]
+pub enum IngestError {
+    #[error("source error: {0}")]
+    Source(String),
+    #[error("invalid proxy url {0}: {1}")]
+    InvalidProxy(String, reqwest::Error),
+    #[error("info request failed: {0}")]
+    Request(#[from] reqwest::Error),
+    #[error("invalid info response: {0}")]
+    InvalidResponse(String),
+    #[error("validation failed: {0}")]
+    Rejected(String),
+    #[error("geolocation failed: {0}")]
+    Geo(#[from] GeoError),
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+// 待导入的代理
+#[derive(Clone)]
+pub struct ProxyCandidate {
+    // 存入 proxies.url 的地址
+    pub url: String,
+    // 获取出口信息时连接的地址，未设置时使用 url
+    pub connect_url: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    // 来自代理商网关时为代理商名
+    pub provider: Option<String>,
+    // 固定国家出口的端口，地理位置查询不到国家代码时使用
+    pub country_hint: Option<String>,
+    // 写入 proxies.tier，free 的代理入库前需要通过校验
+    pub tier: String,
+}
+
+impl std::fmt::Debug for ProxyCandidate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProxyCandidate")
+            .field("url", &redact_proxy_url(&self.url))
+            .field("connect_url", &self.connect_url.as_deref().map(redact_proxy_url))
+            .field("username", &self.username)
+            .field("password", &self.password.as_ref().map(|_| REDACTED))
+            .field("provider", &self.provider)
+            .field("country_hint", &self.country_hint)
+            .field("tier", &self.tier)
+            .finish()
+    }
+}
+
+impl ProxyCandidate {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            connect_url: None,
+            username: None,
+            password: None,
+            provider: None,
+            country_hint: None,
+            tier: TIER_PAID.to_string(),
+        }
+    }
+
+    // 完全相同的候选代理（地址和凭据都一致）只需要处理一次
+    fn dedup_key(&self) -> (String, Option<String>, Option<String>, Option<String>) {
+        (self.url.clone(), self.connect_url.clone(), self.username.clone(), self.password.clone())
+    }
+
+    // 连接该候选代理用的 reqwest 代理配置
+    pub fn proxy(&self) -> Result<Proxy, IngestError> {
+        let connect_url = self.connect_url.as_deref().unwrap_or(&self.url);
+        upstream_proxy(connect_url, self.username.as_deref(), self.password.as_deref())
+            .map_err(|e| IngestError::InvalidProxy(redact_proxy_url(connect_url), e))
+    }
+}
+
+// 单个候选代理的导入失败记录
+#[derive(Debug, Clone)]
+pub struct IngestFailure {
+    pub candidate: String,
+    pub error: String,
+}
+
+// 代理来源给出的候选代理，以及无法解析的条目
+#[derive(Debug, Clone, Default)]
+pub struct CandidateBatch {
+    pub candidates: Vec<ProxyCandidate>,
+    pub rejected: Vec<IngestFailure>,
+}
+
+impl From<Vec<ProxyCandidate>> for CandidateBatch {
+    fn from(candidates: Vec<ProxyCandidate>) -> Self {
+        Self { candidates, rejected: Vec::new() }
+    }
+}
+
+// 代理来源：网关端口段、代理列表文件等
+#[async_trait]
+pub trait ProxySource: Send + Sync {
+    fn name(&self) -> &str;
+
+    async fn candidates(&self) -> Result<CandidateBatch, IngestError>;
+}
+
+// 一次导入的结果统计
+#[derive(Debug, Clone, Default)]
+pub struct IngestReport {
+    pub candidates: usize,
+    pub inserted: usize,
+    pub duplicates: usize,
+    pub failures: Vec<IngestFailure>,
+}
+
+// 通过代理请求 info_url 获取出口IP，再查询地理位置
+pub async fn fetch_ip_info(
+    candidate: &ProxyCandidate,
+    info_url: &str,
+    locator: &dyn GeoLocator,
+) -> Result<IpInfo, IngestError> {
+    let proxy = candidate.proxy()?;
+
+    let ip = fetch_exit_ip(&proxy, info_url).await
+        .map_err(|e| IngestError::InvalidResponse(e.to_string()))?;
+    let geo = locator.locate(&ip, &proxy).await?;
+
+    let code = match &candidate.country_hint {
+        Some(hint) if geo.code.is_empty() => hint.clone(),
+        _ => geo.code,
+    };
+
+    Ok(IpInfo {
+        url: candidate.url.clone(),
+        ip,
+        isp: geo.isp,
+        country: geo.country,
+        latency: 0,
+        code,
+        asn: geo.asn,
+        city: geo.city,
+        region: geo.region,
+        provider: candidate.provider.clone().unwrap_or_default(),
+        username: candidate.username.clone(),
+        password: candidate.password.clone(),
+        tier: candidate.tier.clone(),
+    })
+}
+
+// 写入 proxies 表，(url, ip) 已存在时跳过；返回是否新插入
+pub async fn insert_proxy(ip_info: &IpInfo, anonymity: Option<AnonymityLevel>, db_pool: &PgPool) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO proxies (url, ip, isp, country, latency, code, asn, city, region, provider, username, password, tier, anonymity)
+        VALUES ($1,$2,$3,$4,$5,$6,$7,$8,$9,NULLIF($10, ''),$11,$12,$13,$14)
+        ON CONFLICT (url, ip) DO NOTHING
+        "#,
+        ip_info.url,
+        ip_info.ip,
+        ip_info.isp,
+        ip_info.country,
+        ip_info.latency,
+        ip_info.code,
+        ip_info.asn,
+        ip_info.city,
+        ip_info.region,
+        ip_info.provider,
+        ip_info.username,
+        ip_info.password,
+        ip_info.tier,
+        anonymity.map(|level| level.as_str())
+    ).execute(db_pool).await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+async fn ingest_candidate(
+    candidate: &ProxyCandidate,
+    info_url: &str,
+    locator: &dyn GeoLocator,
+    validator: &Validator,
+    exits: &Mutex<HashSet<(String, String)>>,
+    db_pool: &PgPool,
+) -> Result<bool, IngestError> {
+    let info = fetch_ip_info(candidate, info_url, locator).await?;
+    println!("Get IP infos: {} ({}) via {}", info.ip, info.country, info.redacted_url());
+
+    // 与 proxies 表的唯一键一致：同一地址的不同出口IP分别入库，本次导入中重复的 (url, ip) 不再校验
+    if !exits.lock().unwrap().insert((info.url.clone(), info.ip.clone())) {
+        return Ok(false);
+    }
+
+    // 公共代理只有通过校验才入库
+    let anonymity = if candidate.tier == TIER_FREE {
+        validator.validate(&info, &candidate.proxy()?).await?
+    } else {
+        None
+    };
+
+    Ok(insert_proxy(&info, anonymity, db_pool).await?)
+}
+
+// 从所有来源收集候选代理，并发获取出口信息并写入数据库，等待全部任务完成
+pub async fn run_ingest(
+    sources: &[Box<dyn ProxySource>],
+    info_url: &str,
+    locator: Arc<dyn GeoLocator>,
+    validator: Arc<Validator>,
+    concurrency: usize,
+    db_pool: &PgPool,
+) -> IngestReport {
+    let mut report = IngestReport::default();
+    let semaphore = Arc::new(Semaphore::new(concurrency.max(1)));
+    let info_url = Arc::new(info_url.to_string());
+    let mut tasks = JoinSet::new();
+    let mut seen = HashSet::new();
+    let exits = Arc::new(Mutex::new(HashSet::new()));
+
+    for source in sources {
+        let batch = match source.candidates().await {
+            Ok(batch) => batch,
+            Err(e) => {
+                println!("Proxy source {} failed - {}", source.name(), e);
+                report.failures.push(IngestFailure { candidate: source.name().to_string(), error: e.to_string() });
+                continue;
+            }
+        };
+        println!(
+            "Proxy source {}: {} candidates, {} rejected",
+            source.name(), batch.candidates.len(), batch.rejected.len()
+        );
+        report.candidates += batch.candidates.len();
+        for rejected in batch.rejected {
+            println!("Rejected {} - {}", rejected.candidate, rejected.error);
+            report.failures.push(rejected);
+        }
+
+        for candidate in batch.candidates {
+            // 多个来源或同一列表内完全相同的条目只处理一次；同一地址的网关行出口IP可能不同，获取出口信息后再按 (url, ip) 去重
+            if !seen.insert(candidate.dedup_key()) {
+                report.duplicates += 1;
+                continue;
+            }
+
+            let semaphore = Arc::clone(&semaphore);
+            let info_url = Arc::clone(&info_url);
+            let locator = Arc::clone(&locator);
+            let validator = Arc::clone(&validator);
+            let exits = Arc::clone(&exits);
+            let db_pool = db_pool.clone();
+            tasks.spawn(async move {
+                let _permit = semaphore.acquire_owned().await;
+                let result = ingest_candidate(&candidate, &info_url, locator.as_ref(), &validator, &exits, &db_pool).await;
+                (candidate, result)
+            });
+        }
+    }
+
+    while let Some(joined) = tasks.join_next().await {
+        match joined {
+            Ok((_, Ok(true))) => report.inserted += 1,
+            Ok((_, Ok(false))) => report.duplicates += 1,
+            Ok((candidate, Err(e))) => {
+                let candidate = redact_proxy_url(&candidate.url);
+                println!("Candidate {} failed - {}", candidate, e);
+                report.failures.push(IngestFailure { candidate, error: e.to_string() });
+            }
+            Err(e) => {
+                println!("Ingest task panicked - {}", e);
+                report.failures.push(IngestFailure { candidate: "<task>".to_string(), error: e.to_string() });
+            }
+        }
+    }
+
+    println!(
+        "Ingest finished: {} candidates, {} inserted, {} duplicates, {} failed",
+        report.candidates, report.inserted, report.duplicates, report.failures.len()
+    );
+    report
+}
+
+// 根据配置构建代理来源
+pub fn sources_from_config(config: &IngestConfig, providers: &[ProviderConfig]) -> Vec<Box<dyn ProxySource>> {
+    let mut sources: Vec<Box<dyn ProxySource>> = Vec::new();
+
+    for provider in providers {
+        sources.push(Box::new(ProviderSource::new(provider.clone())));
+    }
+
+    // 未配置 [[providers]] 时使用旧的环境变量网关
+    if providers.is_empty() {
+        match ProviderSource::from_env(config.port_start, config.port_end) {
+            Ok(source) => sources.push(Box::new(source)),
+            Err(e) => println!("Gateway source disabled - {}", e),
+        }
+    }
+
+    for file in &config.files {
+        sources.push(file_source(file));
+    }
+
+    for list in &config.urls {
+        sources.push(Box::new(UrlListSource::new(list)));
+    }
+
+    sources
+}
+
+// 按配置执行一次导入
+pub async fn ingest_once(settings: &Settings) -> Result<IngestReport, IngestError> {
+    dotenv().ok();
+
+    let config = &settings.ingest;
+    let info_url = config.info_url.clone()
+        .or_else(|| env::var("info_url").ok())
+        .ok_or_else(|| IngestError::Source("info_url is not configured".to_string()))?;
+    let locator = locator_from_config(&settings.geo)?;
+    let validator = Arc::new(Validator::from_settings(settings).await?);
+    let pg_url = env::var("DATABASE_URL")
+        .map_err(|_| IngestError::Source("DATABASE_URL is not set".to_string()))?;
+    let pool = PgPool::connect(&pg_url).await?;
+
+    let sources = sources_from_config(config, &settings.providers);
+    let report = run_ingest(&sources, &info_url, locator, validator, config.concurrency, &pool).await;
+
+    pool.close().await;
+    Ok(report)
+}
+
+// 在 roxy 进程内定时导入
+pub async fn run_scheduled_ingest(settings: Settings, shutdown: Shutdown) {
+    let config = &settings.ingest;
+    let mut delay = Duration::from_secs(config.initial_delay_secs);
+
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = shutdown.wait() => return,
+        }
+
+        // 进行中的导入在退出时继续完成
+        let _guard = shutdown.guard();
+        println!("=== Starting scheduled proxy ingest ===");
+        if let Err(e) = ingest_once(&settings).await {
+            println!("Proxy ingest failed - {}", e);
+        }
+        delay = Duration::from_secs(config.interval_secs);
+    }
+}
+
+#[cfg(test)]
+mod test_ingest {
+    use super::*;
+    use crate::geo::GeoInfo;
+    use crate::upstream::basic_authorization;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    struct FixedLocator;
+
+    #[async_trait]
+    impl GeoLocator for FixedLocator {
+        fn name(&self) -> &str {
+            "fixed"
+        }
+
+        async fn locate(&self, _ip: &str, _via: &Proxy) -> Result<GeoInfo, GeoError> {
+            Ok(GeoInfo { isp: "Example ISP".to_string(), country: "Germany".to_string(), code: "DE".to_string(), ..Default::default() })
+        }
+    }
+
+    // 充当上游代理的IP回显服务：带正确的 Proxy-Authorization 时返回出口IP
+    async fn fake_proxy() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0u8; 4096];
+            let n = stream.read(&mut buf).await.unwrap();
+            let request = String::from_utf8_lossy(&buf[..n]).to_lowercase();
+            let expected = format!("proxy-authorization: {}", basic_authorization("user", "p@ss")).to_lowercase();
+            let body = if request.contains(&expected) { "203.0.113.9" } else { "unauthorized" };
+            let response = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body);
+            stream.write_all(response.as_bytes()).await.unwrap();
+        });
+
+        port
+    }
+
+    #[tokio::test]
+    async fn stored_url_has_no_userinfo() {
+        let port = fake_proxy().await;
+        let (candidates, _) = parse_text_list(&format!("user:p%40ss@127.0.0.1:{}", port), "http");
+
+        let info = fetch_ip_info(&candidates[0], "http://ip.example.com/", &FixedLocator).await.unwrap();
+        assert_eq!(info.url, format!("http://127.0.0.1:{}", port));
+        assert_eq!(info.ip, "203.0.113.9");
+        assert_eq!(info.username.as_deref(), Some("user"));
+        assert_eq!(info.password.as_deref(), Some("p@ss"));
+    }
+}
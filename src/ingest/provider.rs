@@ -0,0 +1,173 @@
+use async_trait::async_trait;
+use dotenvy::dotenv;
+use std::env;
+use std::ops::RangeInclusive;
+
+use super::{CandidateBatch, IngestError, IngestFailure, ProxyCandidate, ProxySource};
+use crate::config::{ProviderAuth, ProviderConfig, TIER_PAID};
+use crate::session::USERNAME_TEMPLATE_PREFIX;
+
+// 解析端口段：["10001-10100", "10200"]
+pub fn parse_port_ranges(specs: &[String]) -> Result<Vec<RangeInclusive<u16>>, String> {
+    specs.iter()
+        .map(|spec| {
+            let parse = |s: &str| s.trim().parse::<u16>().map_err(|_| format!("invalid port range: {}", spec));
+            match spec.split_once('-') {
+                Some((start, end)) => {
+                    let (start, end) = (parse(start)?, parse(end)?);
+                    if start > end {
+                        return Err(format!("invalid port range: {}", spec));
+                    }
+                    Ok(start..=end)
+                }
+                None => parse(spec).map(|port| port..=port),
+            }
+        })
+        .collect()
+}
+
+// 替换模板中的 {port}，没有占位符时把端口拼在末尾
+fn expand(template: &str, port: u16) -> String {
+    if template.contains("{port}") {
+        template.replace("{port}", &port.to_string())
+    } else {
+        format!("{}{}", template, port)
+    }
+}
+
+// 代理商网关：按端口段展开，每个端口对应一个出口
+#[derive(Debug, Clone)]
+pub struct ProviderSource {
+    pub provider: ProviderConfig,
+}
+
+impl ProviderSource {
+    pub fn new(provider: ProviderConfig) -> Self {
+        Self { provider }
+    }
+
+    // 兼容旧配置：proxy_url、basic_url、proxy_user、proxy_pass 环境变量加 [ingest] 的端口段
+    // 连接地址为 proxy_url + 端口，入库地址为 basic_url + 端口
+    pub fn from_env(port_start: u16, port_end: u16) -> Result<Self, IngestError> {
+        dotenv().ok();
+
+        let var = |name: &str| env::var(name).map_err(|_| IngestError::Source(format!("{} is not set", name)));
+
+        Ok(Self::new(ProviderConfig {
+            name: "gateway".to_string(),
+            url_template: var("basic_url")?,
+            connect_template: Some(var("proxy_url")?),
+            ports: vec![format!("{}-{}", port_start, port_end)],
+            country_ports: Vec::new(),
+            username: env::var("proxy_user").ok(),
+            username_template: false,
+            password: None,
+            password_env: Some("proxy_pass".to_string()),
+            auth: ProviderAuth::Basic,
+        }))
+    }
+
+    fn candidate(&self, port: u16, country: Option<&str>) -> ProxyCandidate {
+        let provider = &self.provider;
+        let (username, password) = match (&provider.username, provider.auth) {
+            (Some(user), ProviderAuth::Basic) => {
+                // 模板用户名加上前缀存储，转发时按请求参数填充
+                let user = if provider.username_template {
+                    format!("{}{}", USERNAME_TEMPLATE_PREFIX, user)
+                } else {
+                    user.clone()
+                };
+                (Some(user), Some(provider.password().unwrap_or_default()))
+            }
+            _ => (None, None),
+        };
+
+        ProxyCandidate {
+            url: expand(&provider.url_template, port),
+            connect_url: provider.connect_template.as_deref().map(|template| expand(template, port)),
+            username,
+            password,
+            provider: Some(provider.name.clone()),
+            country_hint: country.map(str::to_uppercase),
+            tier: TIER_PAID.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl ProxySource for ProviderSource {
+    fn name(&self) -> &str {
+        &self.provider.name
+    }
+
+    async fn candidates(&self) -> Result<CandidateBatch, IngestError> {
+        let mut batch = CandidateBatch::default();
+
+        let mut groups = vec![(None, &self.provider.ports)];
+        groups.extend(self.provider.country_ports.iter().map(|group| (Some(group.country.as_str()), &group.ports)));
+
+        for (country, specs) in groups {
+            match parse_port_ranges(specs) {
+                Ok(ranges) => {
+                    for port in ranges.into_iter().flatten() {
+                        batch.candidates.push(self.candidate(port, country));
+                    }
+                }
+                Err(error) => batch.rejected.push(IngestFailure {
+                    candidate: format!("provider {}", self.provider.name),
+                    error,
+                }),
+            }
+        }
+
+        Ok(batch)
+    }
+}
+
+#[cfg(test)]
+mod test_provider {
+    use super::*;
+    use crate::config::CountryPorts;
+
+    fn provider(auth: ProviderAuth) -> ProviderConfig {
+        ProviderConfig {
+            name: "vendor".to_string(),
+            url_template: "http://gw.example.com:{port}".to_string(),
+            connect_template: None,
+            ports: vec!["10001-10002".to_string()],
+            country_ports: vec![CountryPorts { country: "de".to_string(), ports: vec!["20001".to_string()] }],
+            username: Some("user".to_string()),
+            username_template: false,
+            password: Some("p@ss".to_string()),
+            password_env: None,
+            auth,
+        }
+    }
+
+    #[test]
+    fn port_ranges() {
+        let specs = vec!["10001-10003".to_string(), "10010".to_string()];
+        assert_eq!(parse_port_ranges(&specs).unwrap(), vec![10001..=10003, 10010..=10010]);
+        assert!(parse_port_ranges(&["10-1".to_string()]).is_err());
+        assert!(parse_port_ranges(&["abc".to_string()]).is_err());
+    }
+
+    #[tokio::test]
+    async fn expands_ports_and_credentials() {
+        let batch = ProviderSource::new(provider(ProviderAuth::Basic)).candidates().await.unwrap();
+        let urls: Vec<&str> = batch.candidates.iter().map(|c| c.url.as_str()).collect();
+        assert_eq!(urls, vec!["http://gw.example.com:10001", "http://gw.example.com:10002", "http://gw.example.com:20001"]);
+        assert_eq!(batch.candidates[0].username.as_deref(), Some("user"));
+        assert_eq!(batch.candidates[2].country_hint.as_deref(), Some("DE"));
+        assert_eq!(batch.candidates[2].provider.as_deref(), Some("vendor"));
+
+        let templated = ProviderConfig { username: Some("user[-country-{country}]".to_string()), username_template: true, ..provider(ProviderAuth::Basic) };
+        let batch = ProviderSource::new(templated).candidates().await.unwrap();
+        assert_eq!(batch.candidates[0].username.as_deref(), Some("template:user[-country-{country}]"));
+        assert_eq!(batch.candidates[0].password.as_deref(), Some("p@ss"));
+
+        let batch = ProviderSource::new(provider(ProviderAuth::None)).candidates().await.unwrap();
+        assert_eq!(batch.candidates[0].url, "http://gw.example.com:10001");
+        assert_eq!(batch.candidates[0].username, None);
+    }
+}
@@ -0,0 +1,60 @@
+use reqwest::Proxy;
+
+use super::IngestError;
+use crate::anonymity::{check_anonymity, AnonymityLevel};
+use crate::config::Settings;
+use crate::exit_ip::fetch_public_ip;
+use crate::structs::IpInfo;
+
+// 公共代理入库前的校验：能转发请求（已获取到出口IP）、出口地理位置、匿名级别
+#[derive(Debug, Clone, Default)]
+pub struct Validator {
+    pub header_echo_url: Option<String>,
+    // Roxy 自身的公网IP，用于识别透明代理
+    pub real_ip: Option<String>,
+    pub min_anonymity: Option<AnonymityLevel>,
+}
+
+impl Validator {
+    pub async fn from_settings(settings: &Settings) -> Result<Self, IngestError> {
+        let probe = &settings.probe;
+        let min_anonymity = settings.ingest.validation.min_anonymity;
+
+        if min_anonymity.is_some() && probe.header_echo_url.is_none() {
+            return Err(IngestError::Source("validation.min_anonymity requires probe.header_echo_url".to_string()));
+        }
+
+        let real_ip = match (&probe.header_echo_url, &probe.ip_echo_url) {
+            (Some(_), Some(echo_url)) => fetch_public_ip(echo_url).await
+                .map_err(|e| println!("Failed to fetch own public IP - {}", e))
+                .ok(),
+            _ => None,
+        };
+
+        Ok(Self {
+            header_echo_url: probe.header_echo_url.clone(),
+            real_ip,
+            min_anonymity,
+        })
+    }
+
+    // 校验通过时返回匿名级别（未配置回显服务时为 None）
+    pub async fn validate(&self, info: &IpInfo, proxy: &Proxy) -> Result<Option<AnonymityLevel>, IngestError> {
+        if info.code.is_empty() {
+            return Err(IngestError::Rejected("exit geolocation unknown".to_string()));
+        }
+
+        let Some(echo_url) = &self.header_echo_url else {
+            return Ok(None);
+        };
+
+        let level = check_anonymity(proxy.clone(), echo_url, self.real_ip.as_deref()).await?;
+        if let Some(min) = self.min_anonymity
+            && level < min
+        {
+            return Err(IngestError::Rejected(format!("anonymity {} is below {}", level.as_str(), min.as_str())));
+        }
+
+        Ok(Some(level))
+    }
+}
@@ -3,13 +3,22 @@ use dotenvy::dotenv;
 use std::env;
 use sqlx::PgPool;
 use std::sync::Arc;
-use tokio::time::{timeout, Duration};
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use tokio::time::{timeout, timeout_at, Duration, Instant};
+use serde::Serialize;
 
-use crate::structs::{IpList};
+use crate::config::{GeoConfig, ProbeConfig};
+use crate::anonymity::probe_anonymity;
+use crate::exit_ip::{fetch_public_ip, verify_exit_ip, ECHO_TIMEOUT};
+use crate::geo::{locator_from_config, HTTP_LOOKUP_TIMEOUT};
+use crate::reachability::probe_targets;
+use crate::structs::ProxyEndpoint;
+use crate::upstream::proxy_host_port;
 
 pub async fn system_ping_latency(ip: &str) -> Result<i32, Box<dyn std::error::Error + Send + Sync>> {
     let ip = ip.to_string();
-    let result = tokio::task::spawn_blocking(move || -> Result<i32, Box<dyn std::error::Error + Send + Sync>> {
+    tokio::task::spawn_blocking(move || -> Result<i32, Box<dyn std::error::Error + Send + Sync>> {
         let output = Command::new("ping")
             .arg("-c")
             .arg("1") 
@@ -21,99 +30,277 @@ pub async fn system_ping_latency(ip: &str) -> Result<i32, Box<dyn std::error::Er
         let stdout = String::from_utf8_lossy(&output.stdout);
         
         for line in stdout.lines() {
-            if line.contains("time=") {
-                if let Some(time_part) = line.split("time=").nth(1) {
-                    if let Some(time_str) = time_part.split_whitespace().next() {
-                        if let Ok(time_f64) = time_str.parse::<f64>() {
-                            return Ok(time_f64 as i32);
-                        }
-                    }
-                }
+            if line.contains("time=")
+                && let Some(time_part) = line.split("time=").nth(1)
+                && let Some(time_str) = time_part.split_whitespace().next()
+                && let Ok(time_f64) = time_str.parse::<f64>()
+            {
+                return Ok(time_f64 as i32);
             }
         }
         
         Err("Could not parse ping output".into())
-    }).await?;
-    
-    result
+    }).await?
 }
 
 pub async fn test_proxy_ip_latency(proxy_ip: &str) -> Result<i32, Box<dyn std::error::Error + Send + Sync>> {
     system_ping_latency(proxy_ip).await
 }
 
-pub async fn update_latency() {
-    println!("Starting latency update with 20s timeout...");
-    
-    // 设置总体超时时间为20秒
-    match timeout(Duration::from_secs(20), update_latency_internal()).await {
-        Ok(_) => println!("Latency update completed successfully!"),
-        Err(_) => println!("Latency update timed out after 20 seconds!"),
+// 连接代理端口的耗时
+pub async fn tcp_connect_latency(proxy_url: &str) -> Result<i32, Box<dyn std::error::Error + Send + Sync>> {
+    let (host, port) = proxy_host_port(proxy_url).ok_or("invalid proxy url")?;
+    let started = std::time::Instant::now();
+    tokio::net::TcpStream::connect((host.as_str(), port)).await?;
+    Ok((started.elapsed().as_millis() as i32).max(1))
+}
+
+const PING_TIMEOUT: Duration = Duration::from_secs(5);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+// 先 ping 代理IP；很多代理商网关不响应 ICMP，ping 不通时再测代理端口的 TCP 连接，都失败才算离线
+async fn probe_latency(proxy: &ProxyEndpoint) -> Result<i32, String> {
+    let ping = match timeout(PING_TIMEOUT, system_ping_latency(&proxy.ip)).await {
+        Ok(Ok(latency)) => return Ok(latency),
+        Ok(Err(e)) => format!("ping failed - {}", e),
+        Err(_) => format!("ping timeout ({}s)", PING_TIMEOUT.as_secs()),
+    };
+    match timeout(CONNECT_TIMEOUT, tcp_connect_latency(&proxy.url)).await {
+        Ok(Ok(latency)) => Ok(latency),
+        Ok(Err(e)) => Err(format!("{}, connect failed - {}", ping, e)),
+        Err(_) => Err(format!("{}, connect timeout ({}s)", ping, CONNECT_TIMEOUT.as_secs())),
     }
 }
 
-async fn update_latency_internal() {
-    let ip_list = get_all_ips().await;
-    println!("Found {} IPs to update", ip_list.len());
+// 单个代理最长的探测时间：延迟，出口IP（变化时重新定位），之后同时探测匿名级别和各目标站点
+fn proxy_probe_budget(probe: &ProbeConfig, geo: &GeoConfig) -> Duration {
+    let mut budget = PING_TIMEOUT + CONNECT_TIMEOUT;
+    if probe.ip_echo_url.is_some() {
+        budget += ECHO_TIMEOUT;
+        if geo.backend == "http" {
+            budget += HTTP_LOOKUP_TIMEOUT;
+        }
+    }
+    let anonymity = if probe.header_echo_url.is_some() { ECHO_TIMEOUT } else { Duration::ZERO };
+    let targets = probe.targets.iter()
+        .map(|target| Duration::from_secs(target.timeout_secs))
+        .max()
+        .unwrap_or_default();
+    budget + anonymity.max(targets)
+}
+
+// 探测周期的超时：配置了 timeout_secs 时直接使用，否则按单个代理的探测时间和需要的轮数计算，
+// 再留出获取 Roxy 自身公网IP的时间
+pub fn cycle_timeout(probe: &ProbeConfig, geo: &GeoConfig, proxies: usize) -> Duration {
+    if let Some(secs) = probe.timeout_secs {
+        return Duration::from_secs(secs);
+    }
+    let rounds = proxies.div_ceil(probe.concurrency.max(1)).max(1) as u32;
+    proxy_probe_budget(probe, geo) * rounds + ECHO_TIMEOUT
+}
+
+// 一次延迟更新的结果统计
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LatencyReport {
+    pub probed: usize,
+    pub up: usize,
+    pub down: usize,
+    pub drifted: usize,
+    pub removed: u64,
+    // 超过周期超时时间，probed 中只有 up + down 个代理完成了探测
+    pub timed_out: bool,
+    // 无法开始探测时的错误（如数据库连接失败）
+    pub error: Option<String>,
+}
+
+pub async fn update_latency(probe: &ProbeConfig, geo: &GeoConfig) -> LatencyReport {
+    println!("Starting latency update...");
     
     dotenv().ok();
+    let pool = match env::var("DATABASE_URL") {
+        Ok(pg_url) => PgPool::connect(&pg_url).await.map_err(|e| e.to_string()),
+        Err(e) => Err(format!("DATABASE_URL: {}", e)),
+    };
+    let pool = match pool {
+        Ok(pool) => Arc::new(pool),
+        Err(e) => {
+            println!("Latency update failed - {}", e);
+            return LatencyReport { error: Some(e), ..Default::default() };
+        }
+    };
+    
+    // 探测任务都放在 probes 里，超时后全部取消并等待结束，避免和下一次探测重叠
+    let mut probes = JoinSet::new();
+    // 超时时保留已完成的探测结果
+    let mut report = LatencyReport::default();
+    
+    let result = update_latency_internal(probe, geo, &pool, &mut probes, &mut report).await;
+    probes.abort_all();
+    while probes.join_next().await.is_some() {}
+    
+    // 确保连接池关闭
+    pool.close().await;
+    
+    match result {
+        Ok(()) if report.timed_out => println!("Latency update timed out, {} of {} proxies probed", report.up + report.down, report.probed),
+        Ok(()) => println!("Latency update completed successfully!"),
+        Err(e) => {
+            println!("Latency update failed - {}", e);
+            report.error = Some(e.to_string());
+        }
+    }
+    report
+}
 
-    let pg_url = env::var("DATABASE_URL").unwrap();
-    let pool = Arc::new(PgPool::connect(&pg_url).await.unwrap());
+async fn update_latency_internal(
+    probe: &ProbeConfig,
+    geo: &GeoConfig,
+    pool: &Arc<PgPool>,
+    probes: &mut JoinSet<(bool, bool)>,
+    report: &mut LatencyReport,
+) -> Result<(), sqlx::Error> {
+    let ip_list = get_all_ips(pool).await?;
+    let cycle_timeout = cycle_timeout(probe, geo, ip_list.len());
+    let deadline = Instant::now() + cycle_timeout;
+    println!("Found {} IPs to update, timeout {}s", ip_list.len(), cycle_timeout.as_secs());
     
-    let mut handles = Vec::new();
+    // Roxy 自身的公网IP，用于判断代理是否透传真实IP
+    let real_ip = match (&probe.header_echo_url, &probe.ip_echo_url) {
+        (Some(_), Some(echo_url)) => fetch_public_ip(echo_url).await
+            .map_err(|e| println!("Failed to fetch own public IP - {}", e))
+            .ok(),
+        _ => None,
+    };
+    let real_ip = Arc::new(real_ip);
 
-    for n in 0..ip_list.len().min(50) {  // 减少并发数量防止过载
-        let ip = ip_list[n].ip.clone();
-        let db_pool = Arc::clone(&pool);
+    // 出口IP变化时重新定位用的地理位置后端
+    let locator = match &probe.ip_echo_url {
+        Some(_) => locator_from_config(geo)
+            .map_err(|e| println!("Geolocation disabled for this cycle - {}", e))
+            .ok(),
+        None => None,
+    };
+
+    let probe = Arc::new(probe.clone());
+    // 每一行都会被探测，信号量只限制同时进行的探测数量，防止过载
+    let permits = Arc::new(Semaphore::new(probe.concurrency.max(1)));
+
+    for mut proxy in ip_list {
+        let ip = proxy.ip.clone();
+        let db_pool = Arc::clone(pool);
+        let probe = Arc::clone(&probe);
+        let real_ip = Arc::clone(&real_ip);
+        let locator = locator.clone();
+        let permits = Arc::clone(&permits);
         
-        let handle = tokio::spawn(async move {
-            // 为单个ping添加5秒超时
-            match timeout(Duration::from_secs(5), system_ping_latency(&ip)).await {
-                Ok(Ok(latency)) => {
+        probes.spawn(async move {
+            let _permit = permits.acquire_owned().await.expect("probe semaphore closed");
+            // 同一网关IP的不同端口是不同的行，按 (url, ip) 更新
+            let up = match probe_latency(&proxy).await {
+                Ok(latency) => {
                     match sqlx::query!(
-                        "UPDATE proxies SET latency = $1 WHERE ip = $2",
+                        "UPDATE proxies SET latency = $1, status = 'up', failure_count = 0 WHERE url = $2 AND ip = $3",
                         latency,
+                        proxy.url,
                         ip
                     ).execute(&*db_pool).await {
                         Ok(_) => println!("IP {}: {}ms - updated", ip, latency),
                         Err(e) => println!("IP {}: database update failed - {}", ip, e),
                     }
+                    true
+                }
+                Err(e) => {
+                    println!("IP {}: {}", ip, e);
+                    mark_proxy_down(&proxy, &db_pool).await;
+                    false
                 }
-                Ok(Err(e)) => println!("IP {}: ping failed - {}", ip, e),
-                Err(_) => println!("IP {}: ping timeout (5s)", ip),
+            };
+
+            // 在线的代理检查出口IP是否变化，再探测匿名级别和各目标站点的可达性
+            let mut drifted = false;
+            if up {
+                if let Some(exit_ip) = verify_exit_ip(&proxy, &probe, locator.as_deref(), &db_pool).await {
+                    proxy.ip = exit_ip;
+                    drifted = true;
+                }
+                tokio::join!(
+                    probe_anonymity(&proxy, &probe, real_ip.as_deref(), &db_pool),
+                    probe_targets(&proxy, &probe.targets, &db_pool),
+                );
             }
+            (up, drifted)
         });
-        
-        handles.push(handle);
     }
 
-    println!("Waiting for {} ping tasks to complete...", handles.len());
-    for handle in handles {
-        let _ = handle.await;
+    println!("Waiting for {} ping tasks to complete...", probes.len());
+    report.probed = probes.len();
+    let collect = async {
+        while let Some(result) = probes.join_next().await {
+            match result {
+                Ok((true, drifted)) => {
+                    report.up += 1;
+                    if drifted {
+                        report.drifted += 1;
+                    }
+                }
+                _ => report.down += 1,
+            }
+        }
+    };
+    if timeout_at(deadline, collect).await.is_err() {
+        report.timed_out = true;
+        return Ok(());
     }
-    
-    // 确保连接池关闭
-    pool.close().await;
+
+    report.removed = remove_failed_proxies(pool, probe.max_failures).await;
     println!("Latency update internal process completed!");
+    Ok(())
 }
 
-async fn get_all_ips() -> Vec<IpList> {
-    dotenv().ok();
-    let pg_url = env::var("DATABASE_URL").unwrap();
-    let pool = PgPool::connect(&pg_url).await.unwrap();
+// 探测失败：标记为down，记录失败时间并累加连续失败次数
+async fn mark_proxy_down(proxy: &ProxyEndpoint, db_pool: &PgPool) {
+    match sqlx::query!(
+        "UPDATE proxies SET status = 'down', last_failure_at = NOW(), failure_count = failure_count + 1 WHERE url = $1 AND ip = $2",
+        proxy.url,
+        proxy.ip
+    ).execute(db_pool).await {
+        Ok(_) => println!("IP {}: marked as down", proxy.ip),
+        Err(e) => println!("IP {}: database update failed - {}", proxy.ip, e),
+    }
+}
 
-    let ip_list = sqlx::query_as!(
-        IpList,
-        "SELECT ip FROM proxies"
-    ).fetch_all(&pool).await.unwrap();
+// 连续失败次数达到上限的代理直接删除（max_failures=0 表示不删除）
+async fn remove_failed_proxies(db_pool: &PgPool, max_failures: i32) -> u64 {
+    if max_failures <= 0 {
+        return 0;
+    }
 
-    ip_list
+    match sqlx::query!(
+        "DELETE FROM proxies WHERE failure_count >= $1",
+        max_failures
+    ).execute(db_pool).await {
+        Ok(result) => {
+            println!("Removed {} proxies after {} consecutive failures", result.rows_affected(), max_failures);
+            result.rows_affected()
+        }
+        Err(e) => {
+            println!("Failed to remove dead proxies - {}", e);
+            0
+        }
+    }
+}
+
+async fn get_all_ips(db_pool: &PgPool) -> Result<Vec<ProxyEndpoint>, sqlx::Error> {
+    sqlx::query_as!(
+        ProxyEndpoint,
+        "SELECT url, ip, username, password FROM proxies"
+    ).fetch_all(db_pool).await
 }
 
 #[cfg(test)]
 mod test_ping {
     use super::*;
+    use crate::config::ProbeTarget;
 
     #[tokio::test]
     async fn test_ping_google_dns() {
@@ -126,8 +313,49 @@ mod test_ping {
         }
     }
 
+    #[tokio::test]
+    async fn falls_back_to_port_connect() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        // 203.0.113.1 不可达，ping 失败后连接代理端口
+        let proxy = ProxyEndpoint {
+            url: format!("http://127.0.0.1:{}", port),
+            ip: "203.0.113.1".to_string(),
+            username: None,
+            password: None,
+        };
+        assert!(probe_latency(&proxy).await.unwrap() > 0);
+
+        drop(listener);
+        assert!(probe_latency(&proxy).await.is_err());
+    }
+
+    #[test]
+    fn cycle_timeout_covers_every_round() {
+        let geo = GeoConfig::default();
+        let mut probe = ProbeConfig::default();
+        // 120 个代理、并发 50：三轮，每轮 ping + 端口连接 10s
+        assert_eq!(cycle_timeout(&probe, &geo, 120), Duration::from_secs(3 * 10 + 5));
+        assert_eq!(cycle_timeout(&probe, &geo, 0), Duration::from_secs(10 + 5));
+
+        probe.ip_echo_url = Some("https://api.ipify.org".to_string());
+        probe.targets = vec![
+            ProbeTarget { name: "a".to_string(), url: "https://a.example".to_string(), expect_status: vec![], body_contains: None, timeout_secs: 5 },
+            ProbeTarget { name: "b".to_string(), url: "https://b.example".to_string(), expect_status: vec![], body_contains: None, timeout_secs: 8 },
+        ];
+        // 10s + 出口IP 5s + 重新定位 30s + 最慢的目标 8s
+        assert_eq!(cycle_timeout(&probe, &geo, 50), Duration::from_secs(53 + 5));
+
+        // 匿名级别探测和目标站点同时进行，只算较慢的一个
+        probe.header_echo_url = Some("http://203.0.113.10:9091/headers".to_string());
+        assert_eq!(cycle_timeout(&probe, &geo, 50), Duration::from_secs(53 + 5));
+
+        probe.timeout_secs = Some(20);
+        assert_eq!(cycle_timeout(&probe, &geo, 500), Duration::from_secs(20));
+    }
+
     #[tokio::test]
     async fn update() {
-        update_latency().await;
+        update_latency(&ProbeConfig::default(), &GeoConfig::default()).await;
     }
 }
@@ -7,5 +7,64 @@ pub use route::*;
 pub mod latency;
 pub use latency::*;
 
+pub mod reachability;
+pub use reachability::*;
+
+pub mod exit_ip;
+pub use exit_ip::*;
+
+pub mod geo;
+pub use geo::*;
+
+pub mod anonymity;
+pub use anonymity::*;
+
+pub mod session;
+pub use session::*;
+
+pub mod upstream_tls;
+pub use upstream_tls::*;
+
+pub mod upstream;
+pub use upstream::*;
+
+pub mod chain;
+pub use chain::*;
+
 pub mod api;
-pub use api::*;
\ No newline at end of file
+pub use api::*;
+
+pub mod shutdown;
+pub use shutdown::*;
+
+pub mod listener;
+pub use listener::*;
+
+#[cfg(unix)]
+pub mod handoff;
+#[cfg(unix)]
+pub use handoff::*;
+
+pub mod server_tls;
+pub use server_tls::*;
+
+pub mod socks;
+pub use socks::*;
+
+pub mod reverse;
+pub use reverse::*;
+
+pub mod db;
+pub use db::*;
+
+pub mod config;
+pub use config::*;
+
+pub mod scheduler;
+pub use scheduler::*;
+
+pub mod admin;
+pub use admin::*;
+
+pub mod ingest;
+pub use ingest::*;
\ No newline at end of file
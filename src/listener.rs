@@ -0,0 +1,211 @@
+use axum::{Extension, Router as AxumRouter};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto::Builder;
+use hyper_util::service::TowerToHyperService;
+use std::io;
+use std::net::SocketAddr;
+#[cfg(unix)]
+use std::os::unix::fs::FileTypeExt;
+use std::path::PathBuf;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream};
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+
+#[cfg(unix)]
+use crate::handoff::{inherited_tcp_listener, inherited_unix_listener, register_listener};
+use crate::shutdown::Shutdown;
+
+// 监听地址：host:port（IPv6 写成 [::]:8080），或 unix:/path/to/roxy.sock
+#[derive(Debug, Clone, PartialEq)]
+pub enum BindAddr {
+    Tcp(String),
+    Unix(PathBuf),
+}
+
+impl BindAddr {
+    pub fn parse(bind: &str) -> Self {
+        match bind.strip_prefix("unix:") {
+            Some(path) => BindAddr::Unix(PathBuf::from(path)),
+            None => BindAddr::Tcp(bind.to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for BindAddr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BindAddr::Tcp(addr) => write!(f, "{}", addr),
+            BindAddr::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+// 客户端地址，作为请求扩展传给处理函数（unix socket 连接没有）
+#[derive(Debug, Clone, Copy)]
+pub struct ClientAddr(pub SocketAddr);
+
+// 给一个连接的 app 加上客户端地址
+pub fn with_client_addr(app: &AxumRouter, peer: Option<SocketAddr>) -> AxumRouter {
+    match peer {
+        Some(peer) => app.clone().layer(Extension(ClientAddr(peer))),
+        None => app.clone(),
+    }
+}
+
+// 绑定 TCP 监听地址；优先使用从 systemd 或旧进程继承的 socket（重启交接）
+pub async fn bind_tcp(bind: &str) -> io::Result<TcpListener> {
+    #[cfg(unix)]
+    if let Some(listener) = inherited_tcp_listener(bind)? {
+        return Ok(listener);
+    }
+
+    let listener = TcpListener::bind(bind).await?;
+    #[cfg(unix)]
+    register_listener(&listener);
+    Ok(listener)
+}
+
+pub enum ProxyListener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+pub enum ProxyStream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl ProxyListener {
+    pub async fn bind(addr: &BindAddr) -> io::Result<Self> {
+        match addr {
+            BindAddr::Tcp(addr) => Ok(ProxyListener::Tcp(bind_tcp(addr).await?)),
+            #[cfg(unix)]
+            BindAddr::Unix(path) => {
+                if let Some(listener) = inherited_unix_listener(path)? {
+                    return Ok(ProxyListener::Unix(listener));
+                }
+                // 上次运行留下的 socket 文件会导致 bind 失败；只删除没有进程监听的 socket，其他文件（包括符号链接）不动
+                match std::fs::symlink_metadata(path) {
+                    Ok(metadata) if !metadata.file_type().is_socket() => {
+                        return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} exists and is not a socket", path.display())));
+                    }
+                    Ok(_) if UnixStream::connect(path).await.is_err() => std::fs::remove_file(path)?,
+                    Ok(_) => {}
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                    Err(e) => return Err(e),
+                }
+                let listener = UnixListener::bind(path)?;
+                register_listener(&listener);
+                Ok(ProxyListener::Unix(listener))
+            }
+            #[cfg(not(unix))]
+            BindAddr::Unix(_) => Err(io::Error::new(io::ErrorKind::Unsupported, "unix sockets are not supported")),
+        }
+    }
+
+    // 返回连接和对端地址（unix socket 没有）
+    pub async fn accept(&self) -> io::Result<(ProxyStream, Option<SocketAddr>)> {
+        match self {
+            ProxyListener::Tcp(listener) => {
+                let (stream, peer) = listener.accept().await?;
+                Ok((ProxyStream::Tcp(stream), Some(peer)))
+            }
+            #[cfg(unix)]
+            ProxyListener::Unix(listener) => {
+                let (stream, _) = listener.accept().await?;
+                Ok((ProxyStream::Unix(stream), None))
+            }
+        }
+    }
+}
+
+// 在一个连接上提供 HTTP/1.1 和 HTTP/2（h2c 或 TLS 上的 ALPN h2），支持 CONNECT 和协议升级
+// 收到退出信号后不再接受新请求（HTTP/1.1 关闭 keep-alive，HTTP/2 发送 GOAWAY），处理中的请求继续完成
+pub async fn serve_connection<S>(stream: S, app: AxumRouter, shutdown: Shutdown) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let _guard = shutdown.guard();
+    let service = TowerToHyperService::new(app);
+    let builder = Builder::new(TokioExecutor::new());
+    let connection = builder.serve_connection_with_upgrades(TokioIo::new(stream), service);
+    tokio::pin!(connection);
+
+    tokio::select! {
+        result = connection.as_mut() => return result,
+        _ = shutdown.wait() => connection.as_mut().graceful_shutdown(),
+    }
+    connection.await
+}
+
+// 接受连接并交给 app 处理，收到退出信号后停止接受
+pub async fn serve_listener(listener: ProxyListener, app: AxumRouter, shutdown: Shutdown) {
+    loop {
+        let accepted = tokio::select! {
+            accepted = listener.accept() => accepted,
+            _ = shutdown.wait() => return,
+        };
+        let (stream, peer) = match accepted {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                println!("Proxy listener accept failed: {}", e);
+                continue;
+            }
+        };
+
+        let app = with_client_addr(&app, peer);
+        let peer = peer.map(|peer| peer.to_string()).unwrap_or_else(|| "unix".to_string());
+        let shutdown = shutdown.clone();
+        tokio::spawn(async move {
+            let result = match stream {
+                ProxyStream::Tcp(stream) => serve_connection(stream, app, shutdown).await,
+                #[cfg(unix)]
+                ProxyStream::Unix(stream) => serve_connection(stream, app, shutdown).await,
+            };
+            if let Err(e) = result {
+                println!("Connection from {} closed: {}", peer, e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod test_listener {
+    use super::*;
+
+    #[test]
+    fn parse_bind_addresses() {
+        assert_eq!(BindAddr::parse("0.0.0.0:8080"), BindAddr::Tcp("0.0.0.0:8080".to_string()));
+        assert_eq!(BindAddr::parse("[::]:8081"), BindAddr::Tcp("[::]:8081".to_string()));
+        assert_eq!(BindAddr::parse("unix:/run/roxy.sock"), BindAddr::Unix(PathBuf::from("/run/roxy.sock")));
+        assert_eq!(BindAddr::parse("unix:/run/roxy.sock").to_string(), "unix:/run/roxy.sock");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn replaces_stale_unix_socket() {
+        let path = std::env::temp_dir().join(format!("roxy-test-{}.sock", std::process::id()));
+        let addr = BindAddr::Unix(path.clone());
+
+        // 第一次 bind 后丢弃监听，socket 文件仍然存在
+        drop(ProxyListener::bind(&addr).await.unwrap());
+        assert!(path.exists());
+        assert!(ProxyListener::bind(&addr).await.is_ok());
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn keeps_files_that_are_not_sockets() {
+        let path = std::env::temp_dir().join(format!("roxy-test-{}.file", std::process::id()));
+        std::fs::write(&path, b"data").unwrap();
+
+        let error = ProxyListener::bind(&BindAddr::Unix(path.clone())).await.err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(std::fs::read(&path).unwrap(), b"data");
+        let _ = std::fs::remove_file(path);
+    }
+}
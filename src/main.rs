@@ -1,333 +1,143 @@
-use roxy::{latency::update_latency};
-use tokio::time::{interval, Duration};
-use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, Ordering};
-use tokio::signal;
-use axum::{
-    extract::{Request, State},
-    http::{HeaderMap, Method, Uri, StatusCode},
-    response::Response,
-    Router as AxumRouter,
-    body::Body,
+use roxy::{
+    admin::start_admin_server,
+    anonymity::start_header_echo_server,
+    api::start_proxy_server_with_settings,
+    config::Settings,
+    db::run_migrations,
+    ingest::run_scheduled_ingest,
+    scheduler::LatencyScheduler,
+    shutdown::{wait_for_signal, Shutdown},
+    socks::start_socks_server,
+    upstream_tls::init_upstream_tls,
 };
-use roxy::route::Router;
-use reqwest::{Client, Proxy};
-use dotenvy::dotenv;
-
-// AppState结构
-#[derive(Clone)]
-#[allow(dead_code)]
-struct AppState {
-    client: Client,
-    router: Router,
-    is_updating: Arc<AtomicBool>,
-}
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::time::Duration;
 
 #[tokio::main]
 async fn main() {
     println!("Starting Roxy Proxy Server with scheduled latency updates...");
-    
-    // 创建一个原子布尔值来控制代理服务的暂停状态
+
+    // 执行数据库迁移
+    if let Err(e) = run_migrations().await {
+        println!("Database migration failed: {}", e);
+    }
+
+    let settings = Settings::load().unwrap_or_else(|e| {
+        println!("Failed to load config, using defaults: {}", e);
+        Settings::default()
+    });
+
+    // 接管 systemd 或正在运行的旧进程的监听 socket，必须在各服务 bind 之前
+    #[cfg(unix)]
+    roxy::handoff::take_over_listeners(&settings.handoff);
+    // 新配置中已经删掉的监听不再占用端口
+    #[cfg(unix)]
+    roxy::handoff::close_unclaimed(&settings.listen_addresses());
+
+    // 上游代理的TLS信任库
+    if let Err(e) = init_upstream_tls(&settings.upstream_tls) {
+        println!("Failed to load upstream TLS config, using built-in roots: {}", e);
+    }
+
+    // 探测周期互斥标志，同一时间只运行一个周期
     let is_updating = Arc::new(AtomicBool::new(false));
-    
+    // 退出信号：各服务停止接受新连接，等待进行中的请求和任务结束
+    let shutdown = Shutdown::new();
+    let scheduler = LatencyScheduler::new(
+        settings.scheduler.clone(),
+        settings.probe.clone(),
+        settings.geo.clone(),
+        Arc::clone(&is_updating),
+        shutdown.clone(),
+    );
+
     // 启动代理服务器
-    let is_updating_clone = Arc::clone(&is_updating);
-    let proxy_handle = tokio::spawn(async move {
-        start_proxy_server_with_pause_check(is_updating_clone).await;
+    let proxy_settings = settings.clone();
+    let proxy_shutdown = shutdown.clone();
+    let mut proxy_handle = tokio::spawn(async move {
+        start_proxy_server_with_settings(proxy_settings, proxy_shutdown).await;
     });
-    
-    // 启动定时延迟更新任务
-    let is_updating_clone = Arc::clone(&is_updating);
-    let update_handle = tokio::spawn(async move {
-        // 等待5分钟后开始第一次更新，然后每15分钟更新一次
-        tokio::time::sleep(Duration::from_secs(300)).await; // 启动后5分钟
-        
-        let mut interval = interval(Duration::from_secs(900)); // 15分钟间隔
-        interval.tick().await; // 跳过第一个立即触发
-        
-        loop {
-            interval.tick().await;
-            
-            println!("=== Starting scheduled latency update ===");
-            
-            // 设置更新标志，暂停代理服务
-            is_updating_clone.store(true, Ordering::SeqCst);
-            println!("Proxy service paused for latency update");
-            
-            // 执行延迟更新
-            update_latency().await;
-            
-            // 清除更新标志，恢复代理服务
-            is_updating_clone.store(false, Ordering::SeqCst);
-            println!("Proxy service resumed");
-            println!("=== Latency update cycle completed ===\n");
-        }
-    });
-    
-    println!("Proxy server and update scheduler started!");
-    println!("- Proxy service: http://0.0.0.0:8080");
-    println!("- First latency update: in 5 minutes");
-    println!("- Update interval: every 15 minutes");
-    println!("- Press Ctrl+C to stop");
-    
-    // 等待Ctrl+C信号
-    tokio::select! {
-        _ = signal::ctrl_c() => {
-            println!("\nShutdown signal received, stopping services...");
-        }
-        _ = proxy_handle => {
-            println!("Proxy server stopped unexpectedly");
-        }
-        _ = update_handle => {
-            println!("Update scheduler stopped unexpectedly");
-        }
-    }
-    
-    println!("Roxy Proxy Server stopped.");
-}
 
-// 修改后的代理服务器启动函数，支持暂停检查
-async fn start_proxy_server_with_pause_check(is_updating: Arc<AtomicBool>) {
-    dotenv().ok();
-    
-    let state = AppState {
-        client: Client::new(),
-        router: Router::new(),
-        is_updating: Arc::clone(&is_updating),
-    };
+    // SOCKS5 入口
+    if settings.socks.enabled {
+        tokio::spawn(start_socks_server(settings.clone(), shutdown.clone()));
+    }
 
-    let app = AxumRouter::new()
-        .fallback(proxy_handler_with_pause_check)
-        .with_state(state);
+    // 启动定时延迟更新任务
+    let mut update_handle = tokio::spawn(scheduler.clone().run());
 
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:8080")
-        .await
-        .unwrap();
-        
-    println!("HTTP Proxy server running on http://0.0.0.0:8080");
-    println!("Strategies (using headers):");
-    println!("  minlatency: curl --proxy http://localhost:8080 https://api.example.com");
-    println!("  random:     curl --proxy http://localhost:8080 -H 'X-Proxy-Strategy: random' https://api.example.com");
-    println!("  country:    curl --proxy http://localhost:8080 -H 'X-Proxy-Strategy: country/DE' https://api.example.com");
-    println!("  binance:    curl --proxy http://localhost:8080 -H 'X-Proxy-Strategy: binance' https://fapi.binance.com/...");
-    
-    axum::serve(listener, app).await.unwrap();
-}
+    // SIGHUP / SIGUSR1 立即触发延迟更新
+    #[cfg(unix)]
+    tokio::spawn(scheduler.clone().listen_for_signals());
 
-// 带暂停检查的代理处理器
-async fn proxy_handler_with_pause_check(
-    State(state): State<AppState>,
-    method: Method,
-    uri: Uri,
-    headers: HeaderMap,
-    request: Request<Body>,
-) -> Result<Response<Body>, StatusCode> {
-    
-    // 检查是否正在更新
-    if state.is_updating.load(Ordering::SeqCst) {
-        println!("Request rejected: Service temporarily unavailable (updating latency)");
-        return Response::builder()
-            .status(StatusCode::SERVICE_UNAVAILABLE)
-            .header("Retry-After", "60")
-            .body(Body::from("Service temporarily unavailable. Please try again in a minute."))
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR);
+    // 请求头回显服务（匿名级别探测）
+    if let Some(bind) = settings.probe.header_echo_bind.clone() {
+        tokio::spawn(start_header_echo_server(bind, shutdown.clone()));
     }
-    
-    println!("DEBUG: Received request - Method: {}, URI: {}", method, uri);
-    
-    // 处理HTTPS CONNECT请求
-    if method == Method::CONNECT {
-        return handle_connect_with_pause_check(state, uri).await;
-    }
-    
-    // 从headers和URL路径中解析策略  
-    let (strategy, country) = parse_strategy_from_request(&headers, uri.path());
-    println!("DEBUG: Final parsed strategy: {}, country: {:?}", strategy, country);
-    
-    handle_proxy_request_with_pause_check(state, method, uri, headers, request, strategy, country).await
-}
 
-// 从headers和URL路径中解析策略
-fn parse_strategy_from_request(headers: &HeaderMap, path: &str) -> (String, Option<String>) {
-    // 首先检查URL路径中的策略（兼容旧格式）
-    if let Some(strategy_from_path) = parse_strategy_from_path(path) {
-        return strategy_from_path;
+    // 定时导入代理
+    if settings.ingest.enabled {
+        tokio::spawn(run_scheduled_ingest(settings.clone(), shutdown.clone()));
     }
-    
-    // 然后检查headers中的策略
-    parse_strategy_from_headers(headers)
-}
 
-// 从URL路径解析策略（支持旧格式）
-fn parse_strategy_from_path(path: &str) -> Option<(String, Option<String>)> {
-    if path.starts_with("/proxy/") {
-        let parts: Vec<&str> = path[7..].split('/').collect(); // 去掉 "/proxy/"
-        if !parts.is_empty() && !parts[0].is_empty() {
-            let strategy = parts[0].to_string();
-            let country = if parts.len() > 1 && !parts[1].is_empty() {
-                Some(parts[1].to_string())
-            } else {
-                None
-            };
-            println!("DEBUG: Parsed strategy from path: {}, country: {:?}", strategy, country);
-            return Some((strategy, country));
-        }
+    // 管理接口
+    if settings.admin.enabled {
+        tokio::spawn(start_admin_server(settings.admin.bind.clone(), scheduler.clone(), shutdown.clone()));
     }
-    None
-}
 
-// 从headers中解析策略
-fn parse_strategy_from_headers(headers: &HeaderMap) -> (String, Option<String>) {
-    // 检查组合策略头 X-Proxy-Strategy: country/DE 或 X-Proxy-Strategy: binance
-    if let Some(strategy_header) = headers.get("X-Proxy-Strategy") {
-        if let Ok(strategy_str) = strategy_header.to_str() {
-            if let Some((strategy, country)) = strategy_str.split_once('/') {
-                return (strategy.to_string(), Some(country.to_string()));
-            } else {
-                return (strategy_str.to_string(), None);
-            }
-        }
+    // 等待下一个进程来接管监听 socket
+    #[cfg(unix)]
+    tokio::spawn(roxy::handoff::serve_handoff(settings.handoff.clone(), shutdown.clone()));
+
+    println!("Proxy server and update scheduler started!");
+    if settings.listeners.is_empty() {
+        println!("- Proxy service: http://0.0.0.0:8080");
     }
-    
-    // 检查分离的国家头
-    if let Some(country_header) = headers.get("X-Proxy-Country") {
-        if let Ok(country_str) = country_header.to_str() {
-            return ("country".to_string(), Some(country_str.to_string()));
-        }
+    for listener in &settings.listeners {
+        println!("- Proxy service: {} (strategy {:?}, country {:?}, profile {:?}, session {:?})",
+            listener.bind, listener.strategy, listener.country, listener.profile, listener.session);
     }
-    
-    // 默认策略
-    ("minlatency".to_string(), None)
-}
-
-// 处理HTTPS CONNECT方法
-async fn handle_connect_with_pause_check(
-    state: AppState,
-    uri: Uri,
-) -> Result<Response<Body>, StatusCode> {
-    
-    let host_port = uri.to_string();
-    println!("CONNECT request to: {}", host_port);
-    
-    // 获取最佳代理
-    let proxy_info = state.router.get_best_proxy().await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-        .ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
-    
-    println!("Using proxy for CONNECT: {} ({}ms)", proxy_info.url, proxy_info.latency);
-    
-    // 对于CONNECT，我们返回200 Connection established
-    Response::builder()
-        .status(StatusCode::OK)
-        .header("Proxy-agent", "roxy/1.0")
-        .body(Body::empty())
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
-}
+    if settings.socks.enabled {
+        println!("- SOCKS5 service: socks5://{}", settings.socks.bind);
+    }
+    if settings.tls.enabled {
+        println!("- HTTPS proxy service: https://{}", settings.tls.bind);
+    }
+    println!("- First latency update: in {} seconds", settings.scheduler.initial_delay_secs);
+    println!("- Update interval: every {} seconds (+0..={}s jitter)", settings.scheduler.interval_secs, settings.scheduler.jitter_secs);
+    if settings.admin.enabled {
+        println!("- Trigger an update: curl -X POST http://{}/admin/latency/run or kill -HUP <pid>", settings.admin.bind);
+    }
+    if let Some(socket) = &settings.handoff.socket {
+        println!("- Zero-downtime restart: start the new binary with the same config, it takes over via {}", socket);
+    }
+    println!("- Press Ctrl+C (or send SIGTERM) to stop");
 
-// 核心代理处理逻辑
-async fn handle_proxy_request_with_pause_check(
-    state: AppState,
-    method: Method,
-    uri: Uri,
-    headers: HeaderMap,
-    request: Request<Body>,
-    strategy: String,
-    country: Option<String>,
-) -> Result<Response<Body>, StatusCode> {
-    
-    // 1. 构建目标URL
-    let target_url = if uri.scheme().is_some() {
-        uri.to_string()
-    } else {
-        let host = headers.get("host")
-            .and_then(|h| h.to_str().ok())
-            .ok_or(StatusCode::BAD_REQUEST)?;
-        // 强制使用HTTPS
-        format!("https://{}{}", host, uri)
-    };
-    
-    println!("Proxying {} request to: {}", method, target_url);
-    println!("Strategy: {}, Country: {:?}", strategy, country);
-    
-    // 2. 根据策略获取代理
-    let proxy_info = match strategy.as_str() {
-        "random" => {
-            state.router.get_random_proxy().await
-                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-        }
-        "country" => {
-            if let Some(country_code) = country {
-                state.router.get_proxy_by_country(&country_code).await
-                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-            } else {
-                return Err(StatusCode::BAD_REQUEST);
-            }
+    // 等待 Ctrl+C 或 SIGTERM
+    tokio::select! {
+        _ = wait_for_signal() => {
+            println!("Shutdown signal received, stopping services...");
         }
-        "binance" => {
-            state.router.get_binance_proxy().await
-                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        _ = shutdown.wait() => {
+            println!("Listening sockets handed over, stopping services...");
         }
-        _ => {
-            state.router.get_best_proxy().await
-                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        _ = &mut proxy_handle => {
+            println!("Proxy server stopped unexpectedly");
         }
-    };
-    
-    let proxy_info = proxy_info.ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
-    println!("Using proxy: {} ({}ms) - {} ({})", proxy_info.url, proxy_info.latency, proxy_info.country, proxy_info.code);
-    
-    // 3. 创建带代理的客户端
-    let proxy = Proxy::all(&proxy_info.url)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    
-    let client = Client::builder()
-        .proxy(proxy)
-        .danger_accept_invalid_certs(true) // 开发环境，生产环境请移除
-        .build()
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    
-    // 4. 读取请求体
-    let body_bytes = axum::body::to_bytes(request.into_body(), usize::MAX).await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    
-    // 5. 构建请求 - 过滤代理专用头
-    let mut req_builder = client.request(method, &target_url);
-    
-    // 复制请求头，但排除代理专用头和标准代理头
-    for (name, value) in headers.iter() {
-        let name_str = name.as_str().to_lowercase();
-        if !["host", "connection", "proxy-connection", "content-length", "proxy-authorization",
-             "x-proxy-strategy", "x-proxy-country"].contains(&name_str.as_str()) {
-            req_builder = req_builder.header(name, value);
+        _ = &mut update_handle => {
+            println!("Update scheduler stopped unexpectedly");
         }
     }
-    
-    // 添加请求体
-    if !body_bytes.is_empty() {
-        req_builder = req_builder.body(body_bytes);
-    }
-    
-    // 6. 发送请求
-    let response = req_builder.send().await
-        .map_err(|e| {
-            println!("Request failed: {}", e);
-            StatusCode::BAD_GATEWAY
-        })?;
-    
-    // 7. 构建响应
-    let status = response.status();
-    let response_headers = response.headers().clone();
-    let response_bytes = response.bytes().await
-        .map_err(|_| StatusCode::BAD_GATEWAY)?;
-    
-    let mut axum_response = Response::builder().status(status);
-    
-    // 复制响应头
-    for (name, value) in response_headers.iter() {
-        axum_response = axum_response.header(name, value);
+
+    // 停止接受新连接和新的延迟更新，等待进行中的请求、隧道和更新结束
+    shutdown.trigger();
+    let drain_timeout = Duration::from_secs(settings.proxy.drain_timeout_secs);
+    println!("Draining {} active connections and tasks (up to {}s)...", shutdown.active(), drain_timeout.as_secs());
+    match tokio::time::timeout(drain_timeout, shutdown.drained()).await {
+        Ok(()) => println!("All connections and tasks finished"),
+        Err(_) => println!("Drain timeout reached, {} connections and tasks still active", shutdown.active()),
     }
-    
-    axum_response
-        .body(Body::from(response_bytes))
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+
+    println!("Roxy Proxy Server stopped.");
 }
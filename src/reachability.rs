@@ -0,0 +1,158 @@
+use futures_util::future::join_all;
+use sqlx::PgPool;
+use std::time::Duration;
+
+use crate::config::ProbeTarget;
+use crate::structs::ProxyEndpoint;
+use crate::upstream_tls::upstream_client_builder;
+
+// 通过代理请求目标URL，返回 (是否可达, 状态码)
+pub async fn probe_target(proxy: &ProxyEndpoint, target: &ProbeTarget) -> Result<(bool, u16), reqwest::Error> {
+    let client = upstream_client_builder()
+        .proxy(proxy.upstream_proxy()?)
+        .timeout(Duration::from_secs(target.timeout_secs))
+        .build()?;
+
+    let response = client.get(&target.url).send().await?;
+    let status = response.status();
+
+    let status_ok = if target.expect_status.is_empty() {
+        status.is_success()
+    } else {
+        target.expect_status.contains(&status.as_u16())
+    };
+
+    let body_ok = match &target.body_contains {
+        Some(expected) if status_ok => response.text().await?.contains(expected.as_str()),
+        _ => true,
+    };
+
+    Ok((status_ok && body_ok, status.as_u16()))
+}
+
+// 同时探测所有目标并写入 proxy_reachability
+pub async fn probe_targets(proxy: &ProxyEndpoint, targets: &[ProbeTarget], db_pool: &PgPool) {
+    join_all(targets.iter().map(|target| probe_and_record(proxy, target, db_pool))).await;
+}
+
+async fn probe_and_record(proxy: &ProxyEndpoint, target: &ProbeTarget, db_pool: &PgPool) {
+    let (reachable, status_code) = match probe_target(proxy, target).await {
+        Ok((reachable, status)) => (reachable, Some(status as i32)),
+        Err(e) => {
+            println!("IP {}: target {} probe failed - {}", proxy.ip, target.name, e);
+            (false, None)
+        }
+    };
+
+    match sqlx::query!(
+        r#"
+        INSERT INTO proxy_reachability (url, ip, target, reachable, status_code, checked_at)
+        VALUES ($1, $2, $3, $4, $5, NOW())
+        ON CONFLICT (url, ip, target) DO UPDATE
+        SET reachable = EXCLUDED.reachable, status_code = EXCLUDED.status_code, checked_at = EXCLUDED.checked_at
+        "#,
+        proxy.url,
+        proxy.ip,
+        target.name,
+        reachable,
+        status_code
+    ).execute(db_pool).await {
+        Ok(_) => println!("IP {}: target {} reachable={}", proxy.ip, target.name, reachable),
+        Err(e) => println!("IP {}: reachability update failed - {}", proxy.ip, e),
+    }
+}
+
+#[cfg(test)]
+mod test_reachability {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    // 对任意请求返回固定状态码和响应体的 HTTP 代理
+    async fn fake_proxy(status: u16, body: &'static str) -> ProxyEndpoint {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buf = vec![0u8; 4096];
+                let _ = stream.read(&mut buf).await.unwrap();
+                let response = format!("HTTP/1.1 {} Test\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", status, body.len(), body);
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        ProxyEndpoint {
+            url: format!("http://127.0.0.1:{}", port),
+            ip: "127.0.0.1".to_string(),
+            username: None,
+            password: None,
+        }
+    }
+
+    fn target(expect_status: &[u16], body_contains: Option<&str>) -> ProbeTarget {
+        ProbeTarget {
+            name: "binance".to_string(),
+            url: "http://api.example.com/ping".to_string(),
+            expect_status: expect_status.to_vec(),
+            body_contains: body_contains.map(str::to_string),
+            timeout_secs: 5,
+        }
+    }
+
+    #[tokio::test]
+    async fn checks_expected_status() {
+        let proxy = fake_proxy(200, "{}").await;
+        assert_eq!(probe_target(&proxy, &target(&[], None)).await.unwrap(), (true, 200));
+
+        // 451 之类的地区限制不算可达
+        let proxy = fake_proxy(451, "restricted").await;
+        assert_eq!(probe_target(&proxy, &target(&[], None)).await.unwrap(), (false, 451));
+
+        let proxy = fake_proxy(404, "").await;
+        assert_eq!(probe_target(&proxy, &target(&[200, 404], None)).await.unwrap(), (true, 404));
+
+        let proxy = fake_proxy(200, "{}").await;
+        assert_eq!(probe_target(&proxy, &target(&[204], None)).await.unwrap(), (false, 200));
+    }
+
+    #[tokio::test]
+    async fn checks_body_contains() {
+        let proxy = fake_proxy(200, r#"{"serverTime":1}"#).await;
+        assert_eq!(probe_target(&proxy, &target(&[], Some("serverTime"))).await.unwrap(), (true, 200));
+
+        let proxy = fake_proxy(200, "Service unavailable from a restricted location").await;
+        assert_eq!(probe_target(&proxy, &target(&[], Some("serverTime"))).await.unwrap(), (false, 200));
+    }
+
+    // 需要数据库，未设置 DATABASE_URL 时跳过
+    #[tokio::test]
+    async fn records_latest_result() {
+        dotenvy::dotenv().ok();
+        let Ok(pg_url) = std::env::var("DATABASE_URL") else {
+            return;
+        };
+        crate::db::run_migrations().await.unwrap();
+        let pool = PgPool::connect(&pg_url).await.unwrap();
+
+        let proxy = fake_proxy(200, "ok").await;
+        let url = proxy.url.clone();
+        sqlx::query!("INSERT INTO proxies (url, ip) VALUES ($1, $2)", url, proxy.ip).execute(&pool).await.unwrap();
+        probe_and_record(&proxy, &target(&[], None), &pool).await;
+        // 同一代理再次探测时更新已有记录
+        probe_and_record(&proxy, &target(&[204], None), &pool).await;
+
+        let row = sqlx::query!(
+            "SELECT reachable, status_code FROM proxy_reachability WHERE url = $1 AND ip = $2 AND target = $3",
+            url,
+            "127.0.0.1",
+            "binance"
+        ).fetch_all(&pool).await.unwrap();
+        sqlx::query!("DELETE FROM proxies WHERE url = $1", url).execute(&pool).await.unwrap();
+        pool.close().await;
+
+        assert_eq!(row.len(), 1);
+        assert!(!row[0].reachable);
+        assert_eq!(row[0].status_code, Some(200));
+    }
+}
@@ -0,0 +1,70 @@
+use reqwest::Url;
+use std::collections::HashMap;
+
+use crate::config::{ReverseRoute, StrategyProfile};
+
+// 检查路由配置，跳过前缀、目标地址或策略配置无效的路由
+pub fn load_reverse_routes(routes: &[ReverseRoute], profiles: &HashMap<String, StrategyProfile>) -> Vec<ReverseRoute> {
+    routes.iter()
+        .filter(|route| {
+            let valid = route.prefix.starts_with('/')
+                && Url::parse(&route.target).is_ok_and(|url| matches!(url.scheme(), "http" | "https"))
+                && route.profile.as_ref().is_none_or(|profile| profiles.contains_key(profile));
+            if !valid {
+                println!("Skipping invalid route {} -> {} (profile {:?})", route.prefix, route.target, route.profile);
+            }
+            valid
+        })
+        .map(|route| ReverseRoute {
+            prefix: route.prefix.trim_end_matches('/').to_string(),
+            target: route.target.trim_end_matches('/').to_string(),
+            profile: route.profile.clone(),
+        })
+        .collect()
+}
+
+// 按最长前缀匹配路由，前缀必须在路径分段处结束；返回路由和去掉前缀后的目标URL
+pub fn match_reverse_route<'a>(routes: &'a [ReverseRoute], path_and_query: &str) -> Option<(&'a ReverseRoute, String)> {
+    routes.iter()
+        .filter_map(|route| {
+            let rest = path_and_query.strip_prefix(route.prefix.as_str())?;
+            if !(rest.is_empty() || rest.starts_with('/') || rest.starts_with('?')) {
+                return None;
+            }
+            let rest = if rest.starts_with('/') { rest.to_string() } else { format!("/{}", rest) };
+            Some((route, format!("{}{}", route.target, rest)))
+        })
+        .max_by_key(|(route, _)| route.prefix.len())
+}
+
+#[cfg(test)]
+mod test_reverse {
+    use super::*;
+
+    fn route(prefix: &str, target: &str) -> ReverseRoute {
+        ReverseRoute { prefix: prefix.to_string(), target: target.to_string(), profile: None }
+    }
+
+    #[test]
+    fn strips_prefix() {
+        let routes = load_reverse_routes(&[
+            route("/binance/", "https://fapi.binance.com/"),
+            route("/binance/spot", "https://api.binance.com"),
+            route("no-slash", "https://example.com"),
+            route("/ftp", "ftp://example.com"),
+        ], &HashMap::new());
+        assert_eq!(routes.len(), 2);
+
+        let (_, url) = match_reverse_route(&routes, "/binance/fapi/v1/time?symbol=BTCUSDT").unwrap();
+        assert_eq!(url, "https://fapi.binance.com/fapi/v1/time?symbol=BTCUSDT");
+
+        let (_, url) = match_reverse_route(&routes, "/binance/spot/api/v3/time").unwrap();
+        assert_eq!(url, "https://api.binance.com/api/v3/time");
+
+        let (_, url) = match_reverse_route(&routes, "/binance?x=1").unwrap();
+        assert_eq!(url, "https://fapi.binance.com/?x=1");
+
+        assert!(match_reverse_route(&routes, "/binancex/fapi").is_none());
+        assert!(match_reverse_route(&routes, "/other").is_none());
+    }
+}
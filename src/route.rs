@@ -3,13 +3,42 @@ use std::env;
 use sqlx::PgPool;
 use rand::Rng;
 
+use crate::anonymity::AnonymityLevel;
+use crate::config::TIER_PAID;
 use crate::structs::IpInfo;
 
+// 策略之外的附加过滤条件（来自 X-Proxy-Anonymity 等请求头）
+#[derive(Debug, Clone, Default)]
+pub struct ProxyFilter {
+    // 最低匿名级别
+    pub anonymity: Option<AnonymityLevel>,
+    // 允许的代理等级；未指定时只用 paid，空列表表示不限
+    pub tiers: Option<Vec<String>>,
+    // 允许的上游协议（http、socks5、socks5h 等）
+    pub schemes: Option<Vec<String>>,
+}
+
+impl ProxyFilter {
+    fn anonymity_levels(&self) -> Option<Vec<String>> {
+        self.anonymity.map(|level| level.at_least())
+    }
+
+    fn tier_list(&self) -> Vec<String> {
+        self.tiers.clone().unwrap_or_else(|| vec![TIER_PAID.to_string()])
+    }
+}
+
 #[derive(Clone)]
 pub struct Router {
     max_latency: i32,
 }
 
+impl Default for Router {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Router {
     pub fn new() -> Self {
         Self {
@@ -18,12 +47,14 @@ impl Router {
     }
 
     // 策略1：最小延迟策略
-    pub async fn get_best_proxy(&self) -> Result<Option<IpInfo>, sqlx::Error> {
+    pub async fn get_best_proxy(&self, filter: &ProxyFilter) -> Result<Option<IpInfo>, sqlx::Error> {
         dotenv().ok();
         let pg_url = env::var("DATABASE_URL").unwrap();
         let pool = PgPool::connect(&pg_url).await?;
+        let anonymity = filter.anonymity_levels();
+        let tiers = filter.tier_list();
 
-        let proxy = sqlx::query_as!(
+        let result = sqlx::query_as!(
             IpInfo,
             r#"
             SELECT 
@@ -32,29 +63,48 @@ impl Router {
                 COALESCE(isp, '') as "isp!",
                 COALESCE(country, '') as "country!",
                 latency as "latency!",
-                COALESCE(code, '') as "code!"
+                COALESCE(code, '') as "code!",
+                COALESCE(asn, 0) as "asn!",
+                COALESCE(city, '') as "city!",
+                COALESCE(region, '') as "region!",
+                COALESCE(provider, '') as "provider!",
+                username,
+                password,
+                tier
             FROM proxies 
             WHERE latency > 0 AND latency < $1 AND latency IS NOT NULL
             AND url IS NOT NULL 
             AND ip IS NOT NULL 
+            AND status = 'up'
+            AND ($2::varchar[] IS NULL OR anonymity = ANY($2))
+            AND (cardinality($3::varchar[]) = 0 OR tier = ANY($3))
+            AND ($4::varchar[] IS NULL OR split_part(url, '://', 1) = ANY($4))
             ORDER BY latency ASC
             LIMIT 1
             "#,
-            self.max_latency
+            self.max_latency,
+            anonymity.as_deref(),
+            &tiers,
+            filter.schemes.as_deref()
         )
         .fetch_optional(&pool)
-        .await?;
+        .await;
+        // 每次选择都新建连接池，用完立即关闭
+        pool.close().await;
+        let proxy = result?;
 
         Ok(proxy)
     }
 
     // 策略2：随机策略（从最快的30个中随机选择）
-    pub async fn get_random_proxy(&self) -> Result<Option<IpInfo>, sqlx::Error> {
+    pub async fn get_random_proxy(&self, filter: &ProxyFilter) -> Result<Option<IpInfo>, sqlx::Error> {
         dotenv().ok();
         let pg_url = env::var("DATABASE_URL").unwrap();
         let pool = PgPool::connect(&pg_url).await?;
+        let anonymity = filter.anonymity_levels();
+        let tiers = filter.tier_list();
 
-        let proxies = sqlx::query_as!(
+        let result = sqlx::query_as!(
             IpInfo,
             r#"
             SELECT 
@@ -63,18 +113,34 @@ impl Router {
                 COALESCE(isp, '') as "isp!",
                 COALESCE(country, '') as "country!",
                 latency as "latency!",
-                COALESCE(code, '') as "code!"
+                COALESCE(code, '') as "code!",
+                COALESCE(asn, 0) as "asn!",
+                COALESCE(city, '') as "city!",
+                COALESCE(region, '') as "region!",
+                COALESCE(provider, '') as "provider!",
+                username,
+                password,
+                tier
             FROM proxies 
             WHERE latency > 0 AND latency < $1 AND latency IS NOT NULL
             AND url IS NOT NULL 
             AND ip IS NOT NULL 
+            AND status = 'up'
+            AND ($2::varchar[] IS NULL OR anonymity = ANY($2))
+            AND (cardinality($3::varchar[]) = 0 OR tier = ANY($3))
+            AND ($4::varchar[] IS NULL OR split_part(url, '://', 1) = ANY($4))
             ORDER BY latency ASC
             LIMIT 30
             "#,
-            self.max_latency
+            self.max_latency,
+            anonymity.as_deref(),
+            &tiers,
+            filter.schemes.as_deref()
         )
         .fetch_all(&pool)
-        .await?;
+        .await;
+        pool.close().await;
+        let proxies = result?;
 
         if proxies.is_empty() {
             return Ok(None);
@@ -87,12 +153,15 @@ impl Router {
     }
 
     // 策略3：国家策略
-    pub async fn get_proxy_by_country(&self, country_code: &str) -> Result<Option<IpInfo>, sqlx::Error> {
+    // 用户名带国家模板（template: 前缀）的网关可以服务任意国家，优先选择出口本就在该国家的代理
+    pub async fn get_proxy_by_country(&self, country_code: &str, filter: &ProxyFilter) -> Result<Option<IpInfo>, sqlx::Error> {
         dotenv().ok();
         let pg_url = env::var("DATABASE_URL").unwrap();
         let pool = PgPool::connect(&pg_url).await?;
+        let anonymity = filter.anonymity_levels();
+        let tiers = filter.tier_list();
 
-        let proxy = sqlx::query_as!(
+        let result = sqlx::query_as!(
             IpInfo,
             r#"
             SELECT 
@@ -101,31 +170,50 @@ impl Router {
                 COALESCE(isp, '') as "isp!",
                 COALESCE(country, '') as "country!",
                 latency as "latency!",
-                COALESCE(code, '') as "code!"
+                COALESCE(code, '') as "code!",
+                COALESCE(asn, 0) as "asn!",
+                COALESCE(city, '') as "city!",
+                COALESCE(region, '') as "region!",
+                COALESCE(provider, '') as "provider!",
+                username,
+                password,
+                tier
             FROM proxies 
             WHERE latency > 0 AND latency < $1 AND latency IS NOT NULL
             AND url IS NOT NULL 
             AND ip IS NOT NULL 
-            AND code = $2
-            ORDER BY latency ASC
+            AND status = 'up'
+            AND ($3::varchar[] IS NULL OR anonymity = ANY($3))
+            AND (cardinality($4::varchar[]) = 0 OR tier = ANY($4))
+            AND ($5::varchar[] IS NULL OR split_part(url, '://', 1) = ANY($5))
+            AND (code = $2 OR username LIKE 'template:%{country}%' OR username LIKE 'template:%{COUNTRY}%')
+            ORDER BY code = $2 DESC, latency ASC
             LIMIT 1
             "#,
             self.max_latency,
-            country_code.to_uppercase()
+            country_code.to_uppercase(),
+            anonymity.as_deref(),
+            &tiers,
+            filter.schemes.as_deref()
         )
         .fetch_optional(&pool)
-        .await?;
+        .await;
+        pool.close().await;
+        let proxy = result?;
 
         Ok(proxy)
     }
 
     // 策略4：Binance策略（排除JP，从最快的20个中随机选择）
-    pub async fn get_binance_proxy(&self) -> Result<Option<IpInfo>, sqlx::Error> {
+    // 配置了 binance 探测目标时，只选择探测可达的代理
+    pub async fn get_binance_proxy(&self, filter: &ProxyFilter) -> Result<Option<IpInfo>, sqlx::Error> {
         dotenv().ok();
         let pg_url = env::var("DATABASE_URL").unwrap();
         let pool = PgPool::connect(&pg_url).await?;
+        let anonymity = filter.anonymity_levels();
+        let tiers = filter.tier_list();
 
-        let proxies = sqlx::query_as!(
+        let result = sqlx::query_as!(
             IpInfo,
             r#"
             SELECT 
@@ -134,19 +222,43 @@ impl Router {
                 COALESCE(isp, '') as "isp!",
                 COALESCE(country, '') as "country!",
                 latency as "latency!",
-                COALESCE(code, '') as "code!"
+                COALESCE(code, '') as "code!",
+                COALESCE(asn, 0) as "asn!",
+                COALESCE(city, '') as "city!",
+                COALESCE(region, '') as "region!",
+                COALESCE(provider, '') as "provider!",
+                username,
+                password,
+                tier
             FROM proxies 
             WHERE latency > 0 AND latency < $1 AND latency IS NOT NULL
             AND url IS NOT NULL 
             AND ip IS NOT NULL 
+            AND status = 'up'
+            AND ($2::varchar[] IS NULL OR anonymity = ANY($2))
+            AND (cardinality($3::varchar[]) = 0 OR tier = ANY($3))
+            AND ($4::varchar[] IS NULL OR split_part(url, '://', 1) = ANY($4))
             AND code != 'JP'
+            AND (
+                NOT EXISTS (SELECT 1 FROM proxy_reachability WHERE target = 'binance')
+                OR EXISTS (
+                    SELECT 1 FROM proxy_reachability r
+                    WHERE r.url = proxies.url AND r.ip = proxies.ip
+                    AND r.target = 'binance' AND r.reachable
+                )
+            )
             ORDER BY latency ASC
             LIMIT 20
             "#,
-            self.max_latency
+            self.max_latency,
+            anonymity.as_deref(),
+            &tiers,
+            filter.schemes.as_deref()
         )
         .fetch_all(&pool)
-        .await?;
+        .await;
+        pool.close().await;
+        let proxies = result?;
 
         if proxies.is_empty() {
             return Ok(None);
@@ -157,19 +269,65 @@ impl Router {
         let index = rng.gen_range(0..proxies.len());
         Ok(Some(proxies[index].clone()))
     }
-}
-
-
-
-
-
-
-
-
-
-
-
 
+    // 策略5：目标策略（只选择探测可达该目标的代理，从最快的20个中随机选择）
+    pub async fn get_proxy_for_target(&self, target: &str, filter: &ProxyFilter) -> Result<Option<IpInfo>, sqlx::Error> {
+        dotenv().ok();
+        let pg_url = env::var("DATABASE_URL").unwrap();
+        let pool = PgPool::connect(&pg_url).await?;
+        let anonymity = filter.anonymity_levels();
+        let tiers = filter.tier_list();
 
+        let result = sqlx::query_as!(
+            IpInfo,
+            r#"
+            SELECT 
+                COALESCE(url, '') as "url!",
+                COALESCE(ip, '') as "ip!",
+                COALESCE(isp, '') as "isp!",
+                COALESCE(country, '') as "country!",
+                latency as "latency!",
+                COALESCE(code, '') as "code!",
+                COALESCE(asn, 0) as "asn!",
+                COALESCE(city, '') as "city!",
+                COALESCE(region, '') as "region!",
+                COALESCE(provider, '') as "provider!",
+                username,
+                password,
+                tier
+            FROM proxies 
+            WHERE latency > 0 AND latency < $1 AND latency IS NOT NULL
+            AND url IS NOT NULL 
+            AND ip IS NOT NULL 
+            AND status = 'up'
+            AND ($3::varchar[] IS NULL OR anonymity = ANY($3))
+            AND (cardinality($4::varchar[]) = 0 OR tier = ANY($4))
+            AND ($5::varchar[] IS NULL OR split_part(url, '://', 1) = ANY($5))
+            AND EXISTS (
+                SELECT 1 FROM proxy_reachability r
+                WHERE r.url = proxies.url AND r.ip = proxies.ip
+                AND r.target = $2 AND r.reachable
+            )
+            ORDER BY latency ASC
+            LIMIT 20
+            "#,
+            self.max_latency,
+            target,
+            anonymity.as_deref(),
+            &tiers,
+            filter.schemes.as_deref()
+        )
+        .fetch_all(&pool)
+        .await;
+        pool.close().await;
+        let proxies = result?;
 
+        if proxies.is_empty() {
+            return Ok(None);
+        }
 
+        let mut rng = rand::thread_rng();
+        let index = rng.gen_range(0..proxies.len());
+        Ok(Some(proxies[index].clone()))
+    }
+}
@@ -0,0 +1,202 @@
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use serde::Serialize;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::sync::RwLock;
+use tokio::time::{sleep, Duration};
+
+use crate::config::{GeoConfig, ProbeConfig, SchedulerConfig};
+use crate::latency::{update_latency, LatencyReport};
+use crate::shutdown::Shutdown;
+
+// 探测周期的触发来源
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TriggerSource {
+    Scheduled,
+    Admin,
+    Signal,
+}
+
+// 最近一次探测周期的状态
+#[derive(Debug, Clone, Serialize)]
+pub struct LastRun {
+    pub trigger: TriggerSource,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub report: LatencyReport,
+}
+
+// 延迟探测调度器：定时执行，也可以通过管理接口或信号立即触发。
+// is_updating 是互斥标志，保证同一时间只有一个探测周期在运行；探测期间代理服务照常工作。
+#[derive(Clone)]
+pub struct LatencyScheduler {
+    config: SchedulerConfig,
+    probe: ProbeConfig,
+    geo: GeoConfig,
+    is_updating: Arc<AtomicBool>,
+    last_run: Arc<RwLock<Option<LastRun>>>,
+    shutdown: Shutdown,
+}
+
+// 探测周期结束时清除 is_updating，周期任务 panic 时也会清除，避免之后的周期一直无法启动
+struct UpdatingFlag(Arc<AtomicBool>);
+
+impl Drop for UpdatingFlag {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
+
+impl LatencyScheduler {
+    pub fn new(config: SchedulerConfig, probe: ProbeConfig, geo: GeoConfig, is_updating: Arc<AtomicBool>, shutdown: Shutdown) -> Self {
+        Self {
+            config,
+            probe,
+            geo,
+            is_updating,
+            last_run: Arc::new(RwLock::new(None)),
+            shutdown,
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.is_updating.load(Ordering::SeqCst)
+    }
+
+    pub async fn last_run(&self) -> Option<LastRun> {
+        self.last_run.read().await.clone()
+    }
+
+    // 立即开始一次探测周期；已有周期在运行或正在退出时返回 false
+    pub fn trigger(&self, source: TriggerSource) -> bool {
+        if self.shutdown.is_triggered() {
+            println!("Shutting down, {:?} latency update ignored", source);
+            return false;
+        }
+        if self.is_updating
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .is_err()
+        {
+            println!("Latency update already running, {:?} trigger ignored", source);
+            return false;
+        }
+
+        let updating = UpdatingFlag(Arc::clone(&self.is_updating));
+        // 退出时等待进行中的探测写完数据库并关闭连接池
+        let guard = self.shutdown.guard();
+        let scheduler = self.clone();
+        tokio::spawn(async move {
+            let _guard = guard;
+            scheduler.run_cycle(source).await;
+            // 清除更新标志，允许下一个周期
+            drop(updating);
+            println!("=== Latency update cycle completed ===\n");
+        });
+        true
+    }
+
+    async fn run_cycle(&self, source: TriggerSource) {
+        println!("=== Starting {:?} latency update ===", source);
+
+        let started_at = Utc::now();
+        let report = update_latency(&self.probe, &self.geo).await;
+        let finished_at = Utc::now();
+
+        self.record_run(LastRun {
+            trigger: source,
+            started_at,
+            finished_at,
+            report,
+        }).await;
+    }
+
+    pub(crate) async fn record_run(&self, run: LastRun) {
+        *self.last_run.write().await = Some(run);
+    }
+
+    // 定时循环：首次等待 initial_delay_secs，之后每 interval_secs (+jitter) 执行一次；收到退出信号后返回
+    pub async fn run(self) {
+        let mut delay = Duration::from_secs(self.config.initial_delay_secs);
+
+        loop {
+            tokio::select! {
+                _ = sleep(delay) => {}
+                _ = self.shutdown.wait() => {
+                    println!("Update scheduler stopped");
+                    return;
+                }
+            }
+            self.trigger(TriggerSource::Scheduled);
+            delay = self.next_interval();
+        }
+    }
+
+    fn next_interval(&self) -> Duration {
+        let jitter = if self.config.jitter_secs > 0 {
+            rand::thread_rng().gen_range(0..=self.config.jitter_secs)
+        } else {
+            0
+        };
+        Duration::from_secs(self.config.interval_secs + jitter)
+    }
+
+    // SIGHUP / SIGUSR1 立即触发一次探测
+    #[cfg(unix)]
+    pub async fn listen_for_signals(self) {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let (mut hup, mut usr1) = match (signal(SignalKind::hangup()), signal(SignalKind::user_defined1())) {
+            (Ok(hup), Ok(usr1)) => (hup, usr1),
+            _ => {
+                println!("Failed to install SIGHUP/SIGUSR1 handlers");
+                return;
+            }
+        };
+
+        loop {
+            tokio::select! {
+                _ = hup.recv() => println!("SIGHUP received, triggering latency update"),
+                _ = usr1.recv() => println!("SIGUSR1 received, triggering latency update"),
+                _ = self.shutdown.wait() => return,
+            }
+            self.trigger(TriggerSource::Signal);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_scheduler {
+    use super::*;
+
+    #[tokio::test]
+    async fn ignores_trigger_while_running() {
+        let is_updating = Arc::new(AtomicBool::new(false));
+        let scheduler = LatencyScheduler::new(
+            SchedulerConfig::default(),
+            ProbeConfig::default(),
+            GeoConfig::default(),
+            Arc::clone(&is_updating),
+            Shutdown::new(),
+        );
+
+        // 单线程运行时里第一次触发的周期还没开始执行
+        assert!(scheduler.trigger(TriggerSource::Admin));
+        assert!(scheduler.is_running());
+        assert!(!scheduler.trigger(TriggerSource::Signal));
+    }
+
+    #[tokio::test]
+    async fn clears_flag_when_cycle_panics() {
+        let is_updating = Arc::new(AtomicBool::new(true));
+        let updating = UpdatingFlag(Arc::clone(&is_updating));
+        let cycle = tokio::spawn(async move {
+            let _updating = updating;
+            panic!("probe failed");
+        });
+
+        assert!(cycle.await.is_err());
+        assert!(!is_updating.load(Ordering::SeqCst));
+    }
+}
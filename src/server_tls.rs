@@ -0,0 +1,186 @@
+use axum::Router as AxumRouter;
+use rustls::crypto::ring;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+use tokio::time::timeout;
+use tokio_rustls::TlsAcceptor;
+
+use crate::config::ListenerTlsConfig;
+use crate::listener::{bind_tcp, serve_connection, with_client_addr};
+use crate::shutdown::Shutdown;
+
+// 客户端完成 TLS 握手的时限
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Error)]
+pub enum ServerTlsError {
+    #[error("failed to read {0}: {1}")]
+    File(String, String),
+    #[error("no certificates found in {0}")]
+    EmptyCertFile(String),
+    #[error("cert_file and key_file are required")]
+    MissingCertificate,
+    #[error("invalid client CA: {0}")]
+    ClientCa(String),
+    #[error("invalid TLS config: {0}")]
+    Rustls(#[from] rustls::Error),
+}
+
+fn load_certs(path: &str) -> Result<Vec<CertificateDer<'static>>, ServerTlsError> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| ServerTlsError::File(path.to_string(), e.to_string()))?;
+    if certs.is_empty() {
+        return Err(ServerTlsError::EmptyCertFile(path.to_string()));
+    }
+    Ok(certs)
+}
+
+// 按配置加载证书和私钥；配置了 client_ca_file 时要求客户端证书（mTLS）
+// ALPN 同时提供 h2 和 http/1.1
+pub fn load_server_config(config: &ListenerTlsConfig) -> Result<ServerConfig, ServerTlsError> {
+    let (cert_file, key_file) = match (&config.cert_file, &config.key_file) {
+        (Some(cert_file), Some(key_file)) => (cert_file, key_file),
+        _ => return Err(ServerTlsError::MissingCertificate),
+    };
+    let certs = load_certs(cert_file)?;
+    let key = PrivateKeyDer::from_pem_file(key_file)
+        .map_err(|e| ServerTlsError::File(key_file.clone(), e.to_string()))?;
+
+    let provider = Arc::new(ring::default_provider());
+    let builder = ServerConfig::builder_with_provider(Arc::clone(&provider))
+        .with_safe_default_protocol_versions()?;
+
+    let builder = match &config.client_ca_file {
+        Some(client_ca_file) => {
+            let mut roots = RootCertStore::empty();
+            roots.add_parsable_certificates(load_certs(client_ca_file)?);
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                .build()
+                .map_err(|e| ServerTlsError::ClientCa(e.to_string()))?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let mut server_config = builder.with_single_cert(certs, key)?;
+    server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(server_config)
+}
+
+// 证书相关文件的最后修改时间，用于判断是否需要重新加载
+fn files_modified(config: &ListenerTlsConfig) -> Vec<Option<SystemTime>> {
+    [&config.cert_file, &config.key_file, &config.client_ca_file]
+        .into_iter()
+        .map(|path| path.as_ref().and_then(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok()))
+        .collect()
+}
+
+// 定期检查证书文件，变化后重新加载；新连接使用新证书，已有连接不受影响
+async fn reload_certificates(config: ListenerTlsConfig, current: Arc<RwLock<Arc<ServerConfig>>>) {
+    let mut modified = files_modified(&config);
+    loop {
+        tokio::time::sleep(Duration::from_secs(config.reload_interval_secs.max(1))).await;
+
+        let latest = files_modified(&config);
+        if latest == modified {
+            continue;
+        }
+        match load_server_config(&config) {
+            Ok(server_config) => {
+                *current.write().unwrap() = Arc::new(server_config);
+                modified = latest;
+                println!("TLS listener certificates reloaded");
+            }
+            // 证书和私钥可能没有同时更新完，下次检查时重试
+            Err(e) => println!("TLS listener certificate reload failed, keeping the current one: {}", e),
+        }
+    }
+}
+
+// HTTPS 代理入口：TLS 终止后与 HTTP 入口使用同一个 app（含 CONNECT 和协议升级）
+pub async fn start_tls_listener(config: ListenerTlsConfig, app: AxumRouter, shutdown: Shutdown) {
+    let server_config = match load_server_config(&config) {
+        Ok(server_config) => server_config,
+        Err(e) => {
+            println!("TLS listener disabled: {}", e);
+            return;
+        }
+    };
+    let current = Arc::new(RwLock::new(Arc::new(server_config)));
+
+    let listener = match bind_tcp(&config.bind).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            println!("TLS listener failed to bind {}: {}", config.bind, e);
+            return;
+        }
+    };
+
+    println!("HTTPS Proxy server running on https://{}{}", config.bind,
+        if config.client_ca_file.is_some() { " (client certificates required)" } else { "" });
+    println!("  curl --proxy https://localhost:{} https://api.example.com", listener.local_addr().map(|a| a.port()).unwrap_or_default());
+
+    if config.reload_interval_secs > 0 {
+        tokio::spawn(reload_certificates(config.clone(), Arc::clone(&current)));
+    }
+
+    loop {
+        let accepted = tokio::select! {
+            accepted = listener.accept() => accepted,
+            _ = shutdown.wait() => return,
+        };
+        let (stream, peer) = match accepted {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                println!("TLS listener accept failed: {}", e);
+                continue;
+            }
+        };
+
+        let acceptor = TlsAcceptor::from(Arc::clone(&current.read().unwrap()));
+        let app = with_client_addr(&app, Some(peer));
+        let shutdown = shutdown.clone();
+        tokio::spawn(async move {
+            // 连接后不发送数据的客户端不能一直占用任务和连接
+            let stream = match timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                Ok(Ok(stream)) => stream,
+                Ok(Err(e)) => {
+                    println!("TLS handshake with {} failed: {}", peer, e);
+                    return;
+                }
+                Err(_) => {
+                    println!("TLS handshake with {} timed out", peer);
+                    return;
+                }
+            };
+
+            if let Err(e) = serve_connection(stream, app, shutdown).await {
+                println!("TLS connection from {} closed: {}", peer, e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod test_server_tls {
+    use super::*;
+
+    #[test]
+    fn requires_certificate_files() {
+        let config = ListenerTlsConfig::default();
+        assert!(matches!(load_server_config(&config), Err(ServerTlsError::MissingCertificate)));
+
+        let config = ListenerTlsConfig {
+            cert_file: Some("/nonexistent/cert.pem".to_string()),
+            key_file: Some("/nonexistent/key.pem".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(load_server_config(&config), Err(ServerTlsError::File(..))));
+    }
+}
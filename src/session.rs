@@ -0,0 +1,149 @@
+use crate::structs::IpInfo;
+
+// 填充代理商用户名模板的参数，每个请求一份
+#[derive(Debug, Clone, Default)]
+pub struct SessionParams {
+    pub strategy: String,
+    pub country: Option<String>,
+    // 来自 X-Proxy-Session，相同会话复用同一出口；为空时由代理商轮换出口
+    pub session: Option<String>,
+}
+
+// 存入 proxies.username 的模板前缀。Basic 认证的用户名不能包含冒号，真实用户名不会以它开头
+pub const USERNAME_TEMPLATE_PREFIX: &str = "template:";
+
+// 标记为模板的用户名，返回去掉前缀的模板
+pub fn username_template(username: &str) -> Option<&str> {
+    username.strip_prefix(USERNAME_TEMPLATE_PREFIX)
+}
+
+// 会话ID和国家代码会拼进代理商用户名，只允许这些字符，
+// 避免客户端借此改写用户名中的其他参数（如 x-country-us）或用冒号破坏 user:pass 的分隔
+pub fn is_valid_session_id(session: &str) -> bool {
+    (1..=64).contains(&session.len()) && session.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_')
+}
+
+pub fn is_valid_country_code(country: &str) -> bool {
+    country.len() == 2 && country.bytes().all(|b| b.is_ascii_alphabetic())
+}
+
+fn placeholder(name: &str, params: &SessionParams) -> Option<String> {
+    match name {
+        "strategy" => Some(params.strategy.clone()),
+        "country" => params.country.as_ref().map(|c| c.to_lowercase()),
+        "COUNTRY" => params.country.as_ref().map(|c| c.to_uppercase()),
+        "session" => params.session.clone(),
+        _ => None,
+    }
+    .filter(|value| !value.is_empty())
+}
+
+// 填充模板：{strategy}、{country}（小写）、{COUNTRY}（大写）、{session}
+// [] 内的片段在任一占位符为空时整体省略，如 user[-country-{country}][-session-{session}]
+pub fn render_credential_template(template: &str, params: &SessionParams) -> String {
+    let mut output = String::new();
+    // 当前 [] 片段的内容，以及片段内是否有空的占位符
+    let mut optional: Option<(String, bool)> = None;
+    let mut chars = template.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '[' if optional.is_none() => optional = Some((String::new(), false)),
+            ']' if optional.is_some() => {
+                if let Some((segment, false)) = optional.take() {
+                    output.push_str(&segment);
+                }
+            }
+            '{' => {
+                let name: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                let value = placeholder(&name, params);
+                match &mut optional {
+                    Some((segment, missing)) => {
+                        *missing |= value.is_none();
+                        segment.push_str(&value.unwrap_or_default());
+                    }
+                    None => output.push_str(&value.unwrap_or_default()),
+                }
+            }
+            _ => match &mut optional {
+                Some((segment, _)) => segment.push(c),
+                None => output.push(c),
+            },
+        }
+    }
+
+    output
+}
+
+// 没有请求参数时（探测、导入）按默认参数填充，占位符为空；密码从不作为模板
+pub fn resolve_username(username: &str) -> String {
+    match username_template(username) {
+        Some(template) => render_credential_template(template, &SessionParams::default()),
+        None => username.to_string(),
+    }
+}
+
+impl IpInfo {
+    // 按本次请求的参数填充用户名模板
+    pub fn for_session(mut self, params: &SessionParams) -> Self {
+        if let Some(username) = &mut self.username
+            && let Some(template) = username_template(username)
+        {
+            *username = render_credential_template(template, params);
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod test_session {
+    use super::*;
+
+    #[test]
+    fn render_templates() {
+        let template = "user[-country-{country}][-session-{session}]";
+        let params = SessionParams {
+            strategy: "country".to_string(),
+            country: Some("DE".to_string()),
+            session: Some("abc123".to_string()),
+        };
+        assert_eq!(render_credential_template(template, &params), "user-country-de-session-abc123");
+
+        let rotating = SessionParams { session: None, ..params.clone() };
+        assert_eq!(render_credential_template(template, &rotating), "user-country-de");
+        assert_eq!(render_credential_template(template, &SessionParams::default()), "user");
+
+        assert_eq!(render_credential_template("cust_{COUNTRY}_{strategy}", &params), "cust_DE_country");
+    }
+
+    #[test]
+    fn validates_session_and_country() {
+        assert!(is_valid_session_id("abc_123"));
+        assert!(is_valid_session_id(&"a".repeat(64)));
+        assert!(!is_valid_session_id(&"a".repeat(65)));
+        assert!(!is_valid_session_id("x-country-us"));
+        assert!(!is_valid_session_id("a:b"));
+        assert!(!is_valid_session_id(""));
+
+        assert!(is_valid_country_code("de"));
+        assert!(is_valid_country_code("US"));
+        assert!(!is_valid_country_code("USA"));
+        assert!(!is_valid_country_code("d1"));
+    }
+
+    #[test]
+    fn only_marked_usernames_are_templates() {
+        let params = SessionParams { country: Some("DE".to_string()), ..Default::default() };
+        let info = IpInfo {
+            username: Some("template:user[-country-{country}]".to_string()),
+            password: Some("p{a}ss".to_string()),
+            ..Default::default()
+        }.for_session(&params);
+        assert_eq!(info.username.as_deref(), Some("user-country-de"));
+        assert_eq!(info.password.as_deref(), Some("p{a}ss"));
+
+        let info = IpInfo { username: Some("us{er}".to_string()), ..Default::default() }.for_session(&params);
+        assert_eq!(info.username.as_deref(), Some("us{er}"));
+        assert_eq!(resolve_username("us{er}"), "us{er}");
+    }
+}
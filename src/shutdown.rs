@@ -0,0 +1,120 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::sync::{watch, Notify};
+
+struct Inner {
+    triggered: watch::Sender<bool>,
+    // 未结束的连接、隧道和后台任务数
+    active: AtomicUsize,
+    idle: Notify,
+}
+
+// 优雅退出：触发后各监听停止接受新连接，已有的连接和任务结束后 drained() 返回
+#[derive(Clone)]
+pub struct Shutdown(Arc<Inner>);
+
+// 持有期间表示有连接或任务未结束
+pub struct DrainGuard(Arc<Inner>);
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        let (triggered, _) = watch::channel(false);
+        Self(Arc::new(Inner {
+            triggered,
+            active: AtomicUsize::new(0),
+            idle: Notify::new(),
+        }))
+    }
+
+    pub fn trigger(&self) {
+        self.0.triggered.send_replace(true);
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        *self.0.triggered.borrow()
+    }
+
+    // 等待退出信号
+    pub async fn wait(&self) {
+        let mut triggered = self.0.triggered.subscribe();
+        let _ = triggered.wait_for(|triggered| *triggered).await;
+    }
+
+    pub fn guard(&self) -> DrainGuard {
+        self.0.active.fetch_add(1, Ordering::SeqCst);
+        DrainGuard(Arc::clone(&self.0))
+    }
+
+    pub fn active(&self) -> usize {
+        self.0.active.load(Ordering::SeqCst)
+    }
+
+    // 等待所有 DrainGuard 释放
+    pub async fn drained(&self) {
+        loop {
+            let idle = self.0.idle.notified();
+            if self.active() == 0 {
+                return;
+            }
+            idle.await;
+        }
+    }
+}
+
+impl Drop for DrainGuard {
+    fn drop(&mut self) {
+        if self.0.active.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.idle.notify_waiters();
+        }
+    }
+}
+
+// Ctrl+C 或 SIGTERM（docker stop）
+pub async fn wait_for_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => println!("\nCtrl+C received"),
+                    _ = terminate.recv() => println!("\nSIGTERM received"),
+                }
+                return;
+            }
+            Err(e) => println!("Failed to install SIGTERM handler: {}", e),
+        }
+    }
+
+    let _ = tokio::signal::ctrl_c().await;
+    println!("\nCtrl+C received");
+}
+
+#[cfg(test)]
+mod test_shutdown {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn drains_after_guards_dropped() {
+        let shutdown = Shutdown::new();
+        let guard = shutdown.guard();
+        let waiter = shutdown.clone();
+        let signal = tokio::spawn(async move { waiter.wait().await });
+
+        shutdown.trigger();
+        signal.await.unwrap();
+        assert!(shutdown.is_triggered());
+
+        assert!(tokio::time::timeout(Duration::from_millis(50), shutdown.drained()).await.is_err());
+        drop(guard);
+        assert!(tokio::time::timeout(Duration::from_millis(50), shutdown.drained()).await.is_ok());
+    }
+}
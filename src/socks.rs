@@ -0,0 +1,547 @@
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use dotenvy::dotenv;
+use reqwest::Url;
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::{lookup_host, TcpStream, UdpSocket};
+use tokio::time::{sleep, timeout};
+
+use crate::api::{parse_selection, select_proxy, AppState, ProxySelection};
+use crate::chain::chain_hops;
+use crate::config::{Settings, SocksConfig};
+use crate::listener::bind_tcp;
+use crate::shutdown::Shutdown;
+use crate::structs::IpInfo;
+use crate::upstream::{open_chain_tunnel, proxy_address, relay_with_idle_timeout, upstream_credentials, TunnelError};
+
+const SOCKS_VERSION: u8 = 5;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+// 客户端完成认证并发送请求的时限
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// 认证方式
+const AUTH_NONE: u8 = 0x00;
+const AUTH_PASSWORD: u8 = 0x02;
+const AUTH_NO_ACCEPTABLE: u8 = 0xFF;
+
+// 命令
+const CMD_CONNECT: u8 = 0x01;
+const CMD_UDP_ASSOCIATE: u8 = 0x03;
+
+// 应答码
+const REP_SUCCEEDED: u8 = 0x00;
+const REP_GENERAL_FAILURE: u8 = 0x01;
+const REP_NOT_ALLOWED: u8 = 0x02;
+const REP_HOST_UNREACHABLE: u8 = 0x04;
+const REP_CONNECTION_REFUSED: u8 = 0x05;
+const REP_COMMAND_NOT_SUPPORTED: u8 = 0x07;
+
+// 用户名中的参数与 HTTP 代理请求头的对应关系
+const USERNAME_KEYS: [(&str, &str); 7] = [
+    ("strategy", "X-Proxy-Strategy"),
+    ("country", "X-Proxy-Country"),
+    ("session", "X-Proxy-Session"),
+    ("profile", "X-Proxy-Profile"),
+    ("tier", "X-Proxy-Tier"),
+    ("scheme", "X-Proxy-Scheme"),
+    ("anonymity", "X-Proxy-Anonymity"),
+];
+
+#[derive(Debug, Error)]
+pub enum SocksError {
+    #[error("socks io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("socks protocol error: {0}")]
+    Protocol(String),
+    #[error("socks authentication failed")]
+    AuthFailed,
+    #[error("socks handshake timed out")]
+    HandshakeTimeout,
+    #[error("invalid socks username: {0}")]
+    InvalidUsername(String),
+}
+
+// 把用户名中的策略参数转换成等价的 X-Proxy-* 请求头，与 HTTP 代理共用解析逻辑
+// 格式为 key-value 对，用 - 连接，例如 country-DE-session-abc123、strategy-target/binance-tier-any
+pub fn headers_from_username(username: &str) -> Result<HeaderMap, SocksError> {
+    let mut headers = HeaderMap::new();
+    if username.is_empty() {
+        return Ok(headers);
+    }
+
+    let parts: Vec<&str> = username.split('-').collect();
+    if !parts.len().is_multiple_of(2) {
+        return Err(SocksError::InvalidUsername(username.to_string()));
+    }
+
+    let mut params = HashMap::new();
+    for pair in parts.chunks(2) {
+        let header = USERNAME_KEYS.iter()
+            .find(|(key, _)| *key == pair[0])
+            .map(|(_, header)| *header)
+            .ok_or_else(|| SocksError::InvalidUsername(username.to_string()))?;
+        params.insert(header, pair[1]);
+    }
+
+    // strategy-country-country-DE 与 country-DE 相同
+    if params.get("X-Proxy-Strategy") == Some(&"country") && params.contains_key("X-Proxy-Country") {
+        params.remove("X-Proxy-Strategy");
+    }
+
+    for (header, value) in params {
+        let value = HeaderValue::from_str(value).map_err(|_| SocksError::InvalidUsername(username.to_string()))?;
+        headers.insert(header, value);
+    }
+    Ok(headers)
+}
+
+// SOCKS5 入口：CONNECT 和 UDP ASSOCIATE，用户名携带策略参数
+pub async fn start_socks_server(settings: Settings, shutdown: Shutdown) {
+    dotenv().ok();
+
+    let state = AppState::new(&settings, shutdown.clone());
+    let config = settings.socks;
+
+    let listener = match bind_tcp(&config.bind).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            println!("SOCKS5 server failed to bind {}: {}", config.bind, e);
+            return;
+        }
+    };
+
+    println!("SOCKS5 proxy server running on socks5://{}", config.bind);
+    println!("  minlatency: curl --proxy socks5h://localhost:1080 https://api.example.com");
+    println!("  country:    curl --proxy socks5h://country-DE:x@localhost:1080 https://api.example.com");
+    println!("  session:    curl --proxy socks5h://country-DE-session-abc123:x@localhost:1080 https://api.example.com");
+
+    let config = Arc::new(config);
+    loop {
+        let accepted = tokio::select! {
+            accepted = listener.accept() => accepted,
+            _ = shutdown.wait() => return,
+        };
+        match accepted {
+            Ok((stream, peer)) => {
+                let state = state.clone();
+                let config = Arc::clone(&config);
+                let guard = shutdown.guard();
+                tokio::spawn(async move {
+                    let _guard = guard;
+                    if let Err(e) = handle_socks_client(stream, peer, state, config).await {
+                        println!("SOCKS5 client {} error: {}", peer, e);
+                    }
+                });
+            }
+            Err(e) => println!("SOCKS5 accept failed: {}", e),
+        }
+    }
+}
+
+async fn handle_socks_client(
+    mut stream: TcpStream,
+    peer: SocketAddr,
+    state: AppState,
+    config: Arc<SocksConfig>,
+) -> Result<(), SocksError> {
+    // 认证和请求必须在时限内读完，空闲的客户端不能一直占用任务，也不能拖住退出时的等待
+    let handshake = async {
+        let selection = negotiate_auth(&mut stream, &state, peer, &config).await?;
+
+        let mut head = [0u8; 3];
+        stream.read_exact(&mut head).await?;
+        if head[0] != SOCKS_VERSION {
+            return Err(SocksError::Protocol(format!("unsupported version {}", head[0])));
+        }
+        let target = read_address(&mut stream).await?;
+        Ok((selection, head[1], target))
+    };
+    let (selection, command, target) = timeout(HANDSHAKE_TIMEOUT, handshake).await
+        .map_err(|_| SocksError::HandshakeTimeout)??;
+
+    match command {
+        CMD_CONNECT => socks_connect(stream, state, selection, &target).await,
+        CMD_UDP_ASSOCIATE => socks_udp_associate(stream, peer, state, selection, &config).await,
+        command => {
+            println!("SOCKS5 command {} not supported", command);
+            send_reply(&mut stream, REP_COMMAND_NOT_SUPPORTED, None).await
+        }
+    }
+}
+
+// 用户名中的策略参数，与 HTTP 代理的请求头使用同样的校验
+fn selection_from_username(state: &AppState, username: &str, peer: SocketAddr) -> Result<ProxySelection, StatusCode> {
+    let headers = headers_from_username(username).map_err(|_| StatusCode::BAD_REQUEST)?;
+    parse_selection(state, &headers, "", Some(peer.ip()))
+}
+
+// 协商认证方式，返回用户名中的代理选择条件（无认证时使用默认值）
+// 密码错误或用户名参数非法（如会话ID、国家代码含非法字符）时认证失败
+async fn negotiate_auth(stream: &mut TcpStream, state: &AppState, peer: SocketAddr, config: &SocksConfig) -> Result<ProxySelection, SocksError> {
+    let mut head = [0u8; 2];
+    stream.read_exact(&mut head).await?;
+    if head[0] != SOCKS_VERSION {
+        return Err(SocksError::Protocol(format!("unsupported version {}", head[0])));
+    }
+    let mut methods = vec![0u8; head[1] as usize];
+    stream.read_exact(&mut methods).await?;
+
+    // 配置了密码时必须认证；否则优先用户名密码认证，以便拿到策略参数
+    let method = if methods.contains(&AUTH_PASSWORD) {
+        AUTH_PASSWORD
+    } else if config.password.is_none() && methods.contains(&AUTH_NONE) {
+        AUTH_NONE
+    } else {
+        AUTH_NO_ACCEPTABLE
+    };
+    stream.write_all(&[SOCKS_VERSION, method]).await?;
+
+    match method {
+        AUTH_NONE => selection_from_username(state, "", peer).map_err(|_| SocksError::InvalidUsername(String::new())),
+        AUTH_PASSWORD => {
+            // RFC 1929: VER ULEN UNAME PLEN PASSWD
+            let mut version = [0u8; 1];
+            stream.read_exact(&mut version).await?;
+            let username = read_string(stream).await?;
+            let password = read_string(stream).await?;
+
+            if config.password.as_ref().is_some_and(|expected| *expected != password) {
+                stream.write_all(&[1, 1]).await?;
+                return Err(SocksError::AuthFailed);
+            }
+            match selection_from_username(state, &username, peer) {
+                Ok(selection) => {
+                    stream.write_all(&[1, 0]).await?;
+                    Ok(selection)
+                }
+                Err(_) => {
+                    stream.write_all(&[1, 1]).await?;
+                    Err(SocksError::InvalidUsername(username))
+                }
+            }
+        }
+        _ => Err(SocksError::AuthFailed),
+    }
+}
+
+async fn read_string<S: AsyncRead + Unpin>(stream: &mut S) -> Result<String, SocksError> {
+    let mut len = [0u8; 1];
+    stream.read_exact(&mut len).await?;
+    let mut value = vec![0u8; len[0] as usize];
+    stream.read_exact(&mut value).await?;
+    String::from_utf8(value).map_err(|_| SocksError::Protocol("invalid utf-8 string".to_string()))
+}
+
+// 读取 ATYP + 地址 + 端口，返回 host:port（IPv6 带方括号）
+pub async fn read_address<S: AsyncRead + Unpin>(stream: &mut S) -> Result<String, SocksError> {
+    let mut atyp = [0u8; 1];
+    stream.read_exact(&mut atyp).await?;
+
+    let host = match atyp[0] {
+        0x01 => {
+            let mut ip = [0u8; 4];
+            stream.read_exact(&mut ip).await?;
+            Ipv4Addr::from(ip).to_string()
+        }
+        0x03 => read_string(stream).await?,
+        0x04 => {
+            let mut ip = [0u8; 16];
+            stream.read_exact(&mut ip).await?;
+            format!("[{}]", Ipv6Addr::from(ip))
+        }
+        atyp => return Err(SocksError::Protocol(format!("unsupported address type {}", atyp))),
+    };
+
+    let mut port = [0u8; 2];
+    stream.read_exact(&mut port).await?;
+    Ok(format!("{}:{}", host, u16::from_be_bytes(port)))
+}
+
+// 编码应答：VER REP RSV ATYP BND.ADDR BND.PORT
+fn encode_reply(rep: u8, bind: Option<SocketAddr>) -> Vec<u8> {
+    let bind = bind.unwrap_or_else(|| SocketAddr::from(([0, 0, 0, 0], 0)));
+    let mut reply = vec![SOCKS_VERSION, rep, 0];
+    match bind.ip() {
+        IpAddr::V4(ip) => {
+            reply.push(0x01);
+            reply.extend_from_slice(&ip.octets());
+        }
+        IpAddr::V6(ip) => {
+            reply.push(0x04);
+            reply.extend_from_slice(&ip.octets());
+        }
+    }
+    reply.extend_from_slice(&bind.port().to_be_bytes());
+    reply
+}
+
+async fn send_reply(stream: &mut TcpStream, rep: u8, bind: Option<SocketAddr>) -> Result<(), SocksError> {
+    stream.write_all(&encode_reply(rep, bind)).await?;
+    Ok(())
+}
+
+fn reply_for_status(status: StatusCode) -> u8 {
+    match status {
+        StatusCode::BAD_REQUEST => REP_NOT_ALLOWED,
+        _ => REP_GENERAL_FAILURE,
+    }
+}
+
+fn reply_for_tunnel_error(error: &TunnelError) -> u8 {
+    match error {
+        TunnelError::Timeout => REP_HOST_UNREACHABLE,
+        TunnelError::Rejected(_) | TunnelError::Socks(_) => REP_CONNECTION_REFUSED,
+        _ => REP_GENERAL_FAILURE,
+    }
+}
+
+// CONNECT：与 HTTP 的 CONNECT 相同，经过（代理链和）选中的代理建立隧道
+async fn socks_connect(
+    mut stream: TcpStream,
+    state: AppState,
+    selection: ProxySelection,
+    target: &str,
+) -> Result<(), SocksError> {
+    println!("SOCKS5 CONNECT request to: {}", target);
+
+    let proxy_info = match select_proxy(&state, &selection).await {
+        Ok(proxy_info) => proxy_info,
+        Err(status) => return send_reply(&mut stream, reply_for_status(status), None).await,
+    };
+    println!("Using proxy for SOCKS5 CONNECT: {} ({}ms)", proxy_info.redacted_url(), proxy_info.latency);
+
+    let hops = chain_hops(&selection.chain, &proxy_info);
+    let upstream = match open_chain_tunnel(&hops, target).await {
+        Ok(upstream) => upstream,
+        Err(e) => {
+            println!("SOCKS5 CONNECT via {} failed: {}", proxy_info.redacted_url(), e);
+            return send_reply(&mut stream, reply_for_tunnel_error(&e), None).await;
+        }
+    };
+
+    let bind = stream.local_addr().ok();
+    send_reply(&mut stream, REP_SUCCEEDED, bind).await?;
+    if let Err(e) = relay_with_idle_timeout(stream, upstream, state.idle_timeout).await {
+        println!("SOCKS5 tunnel to {} closed: {}", target, e);
+    }
+    Ok(())
+}
+
+// UDP ASSOCIATE：选一个 socks5/socks5h 上游，在上游也建立 UDP 关联后原样转发数据报
+// 两端的数据报头格式相同（RSV FRAG ATYP DST.ADDR DST.PORT DATA），不需要改写
+async fn socks_udp_associate(
+    mut stream: TcpStream,
+    peer: SocketAddr,
+    state: AppState,
+    mut selection: ProxySelection,
+    config: &SocksConfig,
+) -> Result<(), SocksError> {
+    // UDP 无法经过 HTTP CONNECT 代理链
+    if !selection.chain.is_empty() {
+        println!("SOCKS5 UDP ASSOCIATE not supported with a proxy chain");
+        return send_reply(&mut stream, REP_COMMAND_NOT_SUPPORTED, None).await;
+    }
+
+    // 只有 SOCKS5 上游支持 UDP
+    let schemes: Vec<String> = match selection.filter.schemes.take() {
+        Some(schemes) => schemes.into_iter().filter(|scheme| scheme.starts_with("socks5")).collect(),
+        None => vec!["socks5".to_string(), "socks5h".to_string()],
+    };
+    if schemes.is_empty() {
+        return send_reply(&mut stream, REP_NOT_ALLOWED, None).await;
+    }
+    selection.filter.schemes = Some(schemes);
+
+    let proxy_info = match select_proxy(&state, &selection).await {
+        Ok(proxy_info) => proxy_info,
+        Err(status) => return send_reply(&mut stream, reply_for_status(status), None).await,
+    };
+    println!("Using proxy for SOCKS5 UDP ASSOCIATE: {} ({}ms)", proxy_info.redacted_url(), proxy_info.latency);
+
+    let (mut control, relay) = match open_udp_associate(&proxy_info).await {
+        Ok(associate) => associate,
+        Err(e) => {
+            println!("SOCKS5 UDP ASSOCIATE via {} failed: {}", proxy_info.redacted_url(), e);
+            return send_reply(&mut stream, reply_for_tunnel_error(&e), None).await;
+        }
+    };
+
+    let client_socket = UdpSocket::bind((stream.local_addr()?.ip(), 0)).await?;
+    let upstream_socket = UdpSocket::bind(if relay.is_ipv6() { "[::]:0" } else { "0.0.0.0:0" }).await?;
+    upstream_socket.connect(relay).await?;
+    send_reply(&mut stream, REP_SUCCEEDED, Some(client_socket.local_addr()?)).await?;
+
+    let idle_timeout = Duration::from_secs(config.udp_idle_timeout_secs);
+    let mut client_addr: Option<SocketAddr> = None;
+    let mut client_buf = vec![0u8; 65535];
+    let mut upstream_buf = vec![0u8; 65535];
+    let mut control_buf = [0u8; 1];
+    let mut upstream_control_buf = [0u8; 1];
+
+    loop {
+        tokio::select! {
+            result = client_socket.recv_from(&mut client_buf) => {
+                let (n, from) = result?;
+                // 只接受建立关联的客户端发来的数据报，不支持分片
+                if from.ip() != peer.ip() || n < 4 || client_buf[2] != 0 {
+                    continue;
+                }
+                client_addr = Some(from);
+                upstream_socket.send(&client_buf[..n]).await?;
+            }
+            result = upstream_socket.recv(&mut upstream_buf) => {
+                let n = result?;
+                if let Some(addr) = client_addr {
+                    client_socket.send_to(&upstream_buf[..n], addr).await?;
+                }
+            }
+            // 任一端的 TCP 控制连接关闭时结束关联
+            _ = stream.read(&mut control_buf) => break,
+            _ = control.read(&mut upstream_control_buf) => break,
+            _ = sleep(idle_timeout) => {
+                println!("SOCKS5 UDP association for {} idle, closing", peer);
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// RFC 1929 用户名密码认证：VER ULEN UNAME PLEN PASSWD，长度超过 255 字节时无法编码
+fn encode_password_auth(username: &str, password: &str) -> Result<Vec<u8>, TunnelError> {
+    let too_long = |_| TunnelError::Socks(tokio_socks::Error::InvalidAuthValues("username and password must be at most 255 bytes"));
+    let mut auth = vec![1, u8::try_from(username.len()).map_err(too_long)?];
+    auth.extend_from_slice(username.as_bytes());
+    auth.push(u8::try_from(password.len()).map_err(too_long)?);
+    auth.extend_from_slice(password.as_bytes());
+    Ok(auth)
+}
+
+// 在 socks5/socks5h 上游建立 UDP 关联，返回需要保持打开的控制连接和上游的 UDP 转发地址
+pub async fn open_udp_associate(proxy: &IpInfo) -> Result<(TcpStream, SocketAddr), TunnelError> {
+    let url = Url::parse(&proxy.url).map_err(|_| TunnelError::InvalidUrl(proxy.redacted_url()))?;
+    if !url.scheme().starts_with("socks5") {
+        return Err(TunnelError::UnsupportedScheme(url.scheme().to_string()));
+    }
+    let (host, port) = proxy_address(proxy)?;
+    let host = host.trim_start_matches('[').trim_end_matches(']').to_string();
+    let credentials = upstream_credentials(&proxy.url, proxy.username.as_deref(), proxy.password.as_deref());
+
+    timeout(CONNECT_TIMEOUT, async {
+        let mut stream = TcpStream::connect((host.as_str(), port)).await?;
+
+        let methods: &[u8] = if credentials.is_some() { &[SOCKS_VERSION, 2, AUTH_NONE, AUTH_PASSWORD] } else { &[SOCKS_VERSION, 1, AUTH_NONE] };
+        stream.write_all(methods).await?;
+        let mut choice = [0u8; 2];
+        stream.read_exact(&mut choice).await?;
+
+        match (choice[1], &credentials) {
+            (AUTH_NONE, _) => {}
+            (AUTH_PASSWORD, Some((username, password))) => {
+                stream.write_all(&encode_password_auth(username, password)?).await?;
+
+                let mut status = [0u8; 2];
+                stream.read_exact(&mut status).await?;
+                if status[1] != 0 {
+                    return Err(TunnelError::AuthRequired);
+                }
+            }
+            _ => return Err(TunnelError::AuthRequired),
+        }
+
+        // 客户端地址未知，按 RFC 1928 发送 0.0.0.0:0
+        stream.write_all(&[SOCKS_VERSION, CMD_UDP_ASSOCIATE, 0, 0x01, 0, 0, 0, 0, 0, 0]).await?;
+        let mut head = [0u8; 3];
+        stream.read_exact(&mut head).await?;
+        if head[1] != REP_SUCCEEDED {
+            return Err(TunnelError::InvalidResponse(format!("UDP ASSOCIATE rejected with reply {}", head[1])));
+        }
+
+        let bind = read_address(&mut stream).await
+            .map_err(|e| TunnelError::InvalidResponse(e.to_string()))?;
+        let mut relay = lookup_host(&bind).await?
+            .next()
+            .ok_or_else(|| TunnelError::InvalidResponse(format!("cannot resolve {}", bind)))?;
+
+        // 上游返回 0.0.0.0 时使用代理自身的地址
+        if relay.ip().is_unspecified() {
+            let proxy_addr = lookup_host((host.as_str(), port)).await?
+                .next()
+                .ok_or_else(|| TunnelError::InvalidResponse(format!("cannot resolve {}", host)))?;
+            relay.set_ip(proxy_addr.ip());
+        }
+
+        Ok((stream, relay))
+    })
+    .await
+    .map_err(|_| TunnelError::Timeout)?
+}
+
+#[cfg(test)]
+mod test_socks {
+    use super::*;
+
+    #[test]
+    fn username_to_headers() {
+        let headers = headers_from_username("country-DE-session-abc123").unwrap();
+        assert_eq!(headers.get("X-Proxy-Country").unwrap(), "DE");
+        assert_eq!(headers.get("X-Proxy-Session").unwrap(), "abc123");
+
+        let headers = headers_from_username("strategy-country-country-DE").unwrap();
+        assert!(headers.get("X-Proxy-Strategy").is_none());
+
+        let headers = headers_from_username("strategy-target/binance-tier-any").unwrap();
+        assert_eq!(headers.get("X-Proxy-Strategy").unwrap(), "target/binance");
+        assert_eq!(headers.get("X-Proxy-Tier").unwrap(), "any");
+
+        assert!(headers_from_username("").unwrap().is_empty());
+        assert!(headers_from_username("country").is_err());
+        assert!(headers_from_username("color-red").is_err());
+    }
+
+    #[tokio::test]
+    async fn reply_address_round_trip() {
+        for bind in ["127.0.0.1:1080", "[::1]:1080"] {
+            let reply = encode_reply(REP_SUCCEEDED, Some(bind.parse().unwrap()));
+            let mut reader = &reply[3..];
+            assert_eq!(read_address(&mut reader).await.unwrap(), bind);
+        }
+    }
+
+    // 用户名中的会话ID或国家代码非法时，用户名密码认证失败
+    #[tokio::test]
+    async fn rejects_unsafe_username_params() {
+        let state = AppState::new(&Settings::default(), Shutdown::new());
+        let config = SocksConfig::default();
+
+        for (username, status) in [("country-DE-session-abc123", 0), ("session-a:b", 1), ("country-DEU", 1)] {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let mut client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+            let (mut server, peer) = listener.accept().await.unwrap();
+
+            let mut request = vec![SOCKS_VERSION, 1, AUTH_PASSWORD, 1, username.len() as u8];
+            request.extend_from_slice(username.as_bytes());
+            request.extend_from_slice(&[1, b'x']);
+            client.write_all(&request).await.unwrap();
+
+            let result = negotiate_auth(&mut server, &state, peer, &config).await;
+            let mut reply = [0u8; 4];
+            client.read_exact(&mut reply).await.unwrap();
+            assert_eq!(reply, [SOCKS_VERSION, AUTH_PASSWORD, 1, status], "{}", username);
+            assert_eq!(result.is_ok(), status == 0);
+        }
+    }
+
+    #[test]
+    fn rejects_overlong_credentials() {
+        assert_eq!(encode_password_auth("user", "pw").unwrap(), b"\x01\x04user\x02pw");
+        assert!(encode_password_auth(&"u".repeat(255), "pw").is_ok());
+        assert!(matches!(encode_password_auth(&"u".repeat(256), "pw"), Err(TunnelError::Socks(_))));
+        assert!(matches!(encode_password_auth("user", &"p".repeat(256)), Err(TunnelError::Socks(_))));
+    }
+}
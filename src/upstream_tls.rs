@@ -0,0 +1,191 @@
+use reqwest::{Certificate, ClientBuilder};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{ring, verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme};
+use std::error::Error as StdError;
+use std::sync::{Arc, OnceLock};
+use thiserror::Error;
+use tokio_rustls::TlsConnector;
+
+use crate::config::UpstreamTlsConfig;
+
+#[derive(Debug, Error)]
+pub enum TlsConfigError {
+    #[error("failed to read CA file {0}: {1}")]
+    CaFile(String, String),
+    #[error("no certificates found in {0}")]
+    EmptyCaFile(String),
+    #[error("invalid TLS config: {0}")]
+    Rustls(#[from] rustls::Error),
+    #[error("upstream TLS is already initialized")]
+    AlreadyInitialized,
+}
+
+// 连接上游代理用的信任库：https:// 代理，以及转发请求时的目标证书校验
+pub struct UpstreamTls {
+    config: UpstreamTlsConfig,
+    ca_certs: Vec<CertificateDer<'static>>,
+    rustls: Arc<ClientConfig>,
+}
+
+static UPSTREAM_TLS: OnceLock<UpstreamTls> = OnceLock::new();
+
+impl UpstreamTls {
+    pub fn load(config: &UpstreamTlsConfig) -> Result<Self, TlsConfigError> {
+        let ca_certs = match &config.ca_file {
+            Some(path) => {
+                let certs = CertificateDer::pem_file_iter(path)
+                    .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+                    .map_err(|e| TlsConfigError::CaFile(path.clone(), e.to_string()))?;
+                if certs.is_empty() {
+                    return Err(TlsConfigError::EmptyCaFile(path.clone()));
+                }
+                certs
+            }
+            None => Vec::new(),
+        };
+
+        let provider = Arc::new(ring::default_provider());
+        let builder = ClientConfig::builder_with_provider(Arc::clone(&provider))
+            .with_safe_default_protocol_versions()?;
+
+        let rustls = if config.accept_invalid_certs {
+            builder.dangerous()
+                .with_custom_certificate_verifier(Arc::new(NoVerifier(provider)))
+                .with_no_client_auth()
+        } else {
+            let mut roots = RootCertStore::empty();
+            if config.builtin_roots {
+                roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+            }
+            roots.add_parsable_certificates(ca_certs.iter().cloned());
+            builder.with_root_certificates(roots).with_no_client_auth()
+        };
+
+        Ok(Self {
+            config: config.clone(),
+            ca_certs,
+            rustls: Arc::new(rustls),
+        })
+    }
+}
+
+// 启动时按配置加载一次；未调用时使用默认配置（内置根证书）
+// 已经加载过（包括已按默认配置使用过）时返回错误，配置不会生效
+pub fn init_upstream_tls(config: &UpstreamTlsConfig) -> Result<(), TlsConfigError> {
+    let tls = UpstreamTls::load(config)?;
+    UPSTREAM_TLS.set(tls).map_err(|_| TlsConfigError::AlreadyInitialized)
+}
+
+fn upstream_tls() -> &'static UpstreamTls {
+    UPSTREAM_TLS.get_or_init(|| {
+        UpstreamTls::load(&UpstreamTlsConfig::default()).expect("default upstream TLS config")
+    })
+}
+
+// 经过上游代理发请求的客户端，信任库与 CONNECT 隧道相同
+pub fn upstream_client_builder() -> ClientBuilder {
+    let tls = upstream_tls();
+    let mut builder = reqwest::Client::builder()
+        .tls_built_in_root_certs(tls.config.builtin_roots)
+        .danger_accept_invalid_certs(tls.config.accept_invalid_certs);
+
+    for cert in &tls.ca_certs {
+        if let Ok(cert) = Certificate::from_der(cert) {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+    builder
+}
+
+pub fn tls_connector() -> TlsConnector {
+    TlsConnector::from(Arc::clone(&upstream_tls().rustls))
+}
+
+// 从错误链中找出证书校验失败的原因（rustls::Error::InvalidCertificate）
+pub fn certificate_error(error: &(dyn StdError + 'static)) -> Option<String> {
+    let mut current = Some(error);
+    while let Some(error) = current {
+        if let Some(rustls::Error::InvalidCertificate(reason)) = error.downcast_ref::<rustls::Error>() {
+            return Some(format!("{:?}", reason));
+        }
+        // io::Error 的 source() 会跳过它包装的错误本身，rustls 的错误需要通过 get_ref 取出
+        if let Some(inner) = error.downcast_ref::<std::io::Error>().and_then(|e| e.get_ref())
+            && let Some(reason) = certificate_error(inner)
+        {
+            return Some(reason);
+        }
+        current = error.source();
+    }
+    None
+}
+
+// accept_invalid_certs = true 时不校验证书（仅用于调试）
+#[derive(Debug)]
+struct NoVerifier(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for NoVerifier {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod test_upstream_tls {
+    use super::*;
+    use rustls::CertificateError;
+    use std::io;
+
+    #[derive(Debug, Error)]
+    #[error("request failed")]
+    struct Wrapped(#[source] io::Error);
+
+    #[test]
+    fn finds_certificate_errors_in_chain() {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, rustls::Error::InvalidCertificate(CertificateError::UnknownIssuer));
+        assert_eq!(certificate_error(&invalid()).as_deref(), Some("UnknownIssuer"));
+        assert_eq!(certificate_error(&Wrapped(invalid())).as_deref(), Some("UnknownIssuer"));
+
+        // 只是消息里提到 certificate 的其他错误不算证书校验失败
+        let other = io::Error::other("client certificate required by proxy");
+        assert_eq!(certificate_error(&Wrapped(other)), None);
+        assert_eq!(certificate_error(&rustls::Error::DecryptError), None);
+    }
+
+    #[test]
+    fn second_init_is_rejected() {
+        let _ = init_upstream_tls(&UpstreamTlsConfig::default());
+        assert!(matches!(init_upstream_tls(&UpstreamTlsConfig::default()), Err(TlsConfigError::AlreadyInitialized)));
+    }
+}